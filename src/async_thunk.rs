@@ -0,0 +1,273 @@
+//! Lazily evaluated values produced by futures.
+
+use std::cell::UnsafeCell;
+use std::future::{Future, IntoFuture};
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+use crate::{Panic, ThunkState};
+
+// The future has never been polled.
+const UNEVALUATED: usize = 0;
+// A task is polling the future.
+const POLLING: usize = 1;
+const EVALUATED: usize = 2;
+const POISONED: usize = 3;
+// The future has been polled, but no task is currently polling it.
+const IDLE: usize = 4;
+// A task is polling the future, and
+// the future has been woken up during that poll.
+const REPOLL: usize = 5;
+
+/// A lazily evaluated value produced by a future.
+///
+/// Any number of tasks may await the value concurrently.
+/// The future is polled by only one of these tasks at a time,
+/// and every waiting task is woken up when the future makes progress.
+///
+/// ~~~
+/// # use lazy_mt::AsyncThunk;
+/// # use std::{future::Future, pin::pin, sync::Arc, task::{Context, Poll, Wake}, thread};
+/// # struct Unpark(thread::Thread);
+/// # impl Wake for Unpark {
+/// #     fn wake(self: Arc<Self>) { self.0.unpark() }
+/// # }
+/// # fn block_on<F: Future>(f: F) -> F::Output {
+/// #     let waker = Arc::new(Unpark(thread::current())).into();
+/// #     let mut cx = Context::from_waker(&waker);
+/// #     let mut f = pin!(f);
+/// #     loop {
+/// #         match f.as_mut().poll(&mut cx) {
+/// #             Poll::Ready(x) => return x,
+/// #             Poll::Pending => thread::park(),
+/// #         }
+/// #     }
+/// # }
+/// let x = Arc::new(AsyncThunk::new(async { println!("Evaluated!"); 7 }));
+/// let y = x.clone();
+///
+/// // "Evaluated!" is printed only once.
+/// let handle = thread::spawn(move || *block_on(y.force()));
+/// assert_eq!(*block_on(x.force()), 7);
+/// assert_eq!(handle.join().unwrap(), 7);
+/// ~~~
+pub struct AsyncThunk<F: Future> {
+    shared: Arc<Shared>,
+    future: UnsafeCell<Option<Pin<Box<F>>>>,
+    value: UnsafeCell<Option<F::Output>>,
+}
+
+// `future` is only accessed by the task that set the state to `POLLING`,
+// and `value` is only written by that task and
+// only read once the state is `EVALUATED`.
+unsafe impl<F: Future + Send> Sync for AsyncThunk<F> where F::Output: Send + Sync {}
+
+/// The part of an `AsyncThunk` that is shared with the waker of its future.
+struct Shared {
+    state: AtomicUsize,
+    waiters: Mutex<Waiters>,
+}
+
+struct Waiters {
+    wakers: Vec<Waker>,
+    /// The panic that poisoned the thunk, if any.
+    panic: Option<Panic>,
+}
+
+impl Shared {
+    fn wake_all(&self) {
+        let wakers = std::mem::take(&mut self.waiters.lock().unwrap().wakers);
+        wakers.into_iter().for_each(Waker::wake)
+    }
+
+    fn panic(&self) -> Panic {
+        self.waiters.lock().unwrap().panic.clone().unwrap()
+    }
+}
+
+impl Wake for Shared {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        // If a task is currently polling the future, make it poll again;
+        // otherwise, wake up all tasks to let one of them poll the future.
+        let cas = self
+            .state
+            .compare_exchange(POLLING, REPOLL, Ordering::AcqRel, Ordering::Acquire);
+        if cas == Err(IDLE) {
+            self.wake_all()
+        }
+    }
+}
+
+impl<F: Future> AsyncThunk<F> {
+    /// Create a lazily evaluated value from a future.
+    ///
+    /// The future is not polled before the value is awaited.
+    pub fn new(future: F) -> Self {
+        Self::with_state(UNEVALUATED, Some(Box::pin(future)), None)
+    }
+
+    /// Create a new, evaluated, thunk from a value.
+    pub fn evaluated(val: F::Output) -> Self {
+        Self::with_state(EVALUATED, None, Some(val))
+    }
+
+    fn with_state(state: usize, future: Option<Pin<Box<F>>>, val: Option<F::Output>) -> Self {
+        let waiters = Waiters {
+            wakers: Vec::new(),
+            panic: None,
+        };
+        AsyncThunk {
+            shared: Arc::new(Shared {
+                state: AtomicUsize::new(state),
+                waiters: Mutex::new(waiters),
+            }),
+            future: UnsafeCell::new(future),
+            value: UnsafeCell::new(val),
+        }
+    }
+
+    /// Return a future that evaluates the thunk and yields its value.
+    ///
+    /// # Panics
+    ///
+    /// If polling the future of the thunk panicked,
+    /// the returned future resumes that panic when polled.
+    pub fn force(&self) -> Force<'_, F> {
+        Force(self)
+    }
+
+    /// Return the current evaluation state of the thunk,
+    /// without polling its future.
+    pub fn state(&self) -> ThunkState {
+        match self.shared.state.load(Ordering::Acquire) {
+            UNEVALUATED => ThunkState::Unevaluated,
+            EVALUATED => ThunkState::Evaluated,
+            POISONED => ThunkState::Poisoned,
+            _ => ThunkState::Evaluating,
+        }
+    }
+
+    /// Return true if the thunk has been evaluated.
+    pub fn is_evaluated(&self) -> bool {
+        self.shared.state.load(Ordering::Acquire) == EVALUATED
+    }
+
+    /// Return the value of the thunk if it has been evaluated,
+    /// without polling its future.
+    pub fn get(&self) -> Option<&F::Output> {
+        if !self.is_evaluated() {
+            return None;
+        }
+        // Safe because the thunk is evaluated,
+        // so `value` is never written to again while `self` is borrowed.
+        unsafe { &*self.value.get() }.as_ref()
+    }
+
+    fn poll_force(&self, cx: &mut Context) -> Poll<&F::Output> {
+        let shared = &self.shared;
+        if let Some(val) = self.get() {
+            return Poll::Ready(val);
+        }
+
+        let mut waiters = shared.waiters.lock().unwrap();
+        if !waiters.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            waiters.wakers.push(cx.waker().clone());
+        }
+        drop(waiters);
+
+        // Claim the polling of the future.
+        let mut state = shared.state.load(Ordering::Acquire);
+        loop {
+            state = match state {
+                UNEVALUATED | IDLE => {
+                    let cas = shared.state.compare_exchange_weak(
+                        state,
+                        POLLING,
+                        Ordering::Acquire,
+                        Ordering::Acquire,
+                    );
+                    match cas {
+                        Ok(_) => break,
+                        Err(state) => state,
+                    }
+                }
+                // Because we registered our waker before, we are woken up
+                // once the future completes or is woken up.
+                POLLING | REPOLL => return Poll::Pending,
+                EVALUATED => return Poll::Ready(self.get().unwrap()),
+                _ => shared.panic().resume(),
+            }
+        }
+
+        let waker = Waker::from(shared.clone());
+        let mut fcx = Context::from_waker(&waker);
+        // Safe because we set the state to `POLLING`,
+        // so no other task accesses `future` until we are done.
+        let future = unsafe { &mut *self.future.get() }.as_mut().unwrap();
+        match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(&mut fcx))) {
+            Ok(Poll::Ready(val)) => {
+                // Safe for the same reason as above.
+                unsafe {
+                    *self.value.get() = Some(val);
+                    *self.future.get() = None;
+                }
+                shared.state.store(EVALUATED, Ordering::Release);
+                shared.wake_all();
+                Poll::Ready(self.get().unwrap())
+            }
+            Ok(Poll::Pending) => {
+                let cas = shared.state.compare_exchange(
+                    POLLING,
+                    IDLE,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                );
+                if cas.is_err() {
+                    // The future was woken up while we polled it.
+                    // Yield to the executor before polling it again,
+                    // so that futures that yield cooperatively do not spin.
+                    shared.state.store(IDLE, Ordering::Release);
+                    cx.waker().wake_by_ref();
+                }
+                Poll::Pending
+            }
+            Err(payload) => {
+                // Safe for the same reason as above.
+                unsafe { *self.future.get() = None };
+                shared.waiters.lock().unwrap().panic = Some(Panic::new(&*payload));
+                shared.state.store(POISONED, Ordering::Release);
+                shared.wake_all();
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+}
+
+/// A future that evaluates an `AsyncThunk` and yields its value.
+///
+/// This is returned by `AsyncThunk::force`.
+pub struct Force<'a, F: Future>(&'a AsyncThunk<F>);
+
+impl<'a, F: Future> Future for Force<'a, F> {
+    type Output = &'a F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.0.poll_force(cx)
+    }
+}
+
+impl<'a, F: Future> IntoFuture for &'a AsyncThunk<F> {
+    type Output = &'a F::Output;
+    type IntoFuture = Force<'a, F>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        self.force()
+    }
+}
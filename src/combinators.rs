@@ -0,0 +1,204 @@
+//! Combinators that compose thunks without forcing them.
+//!
+//! The combinators on `Thunk` consume their thunks and
+//! return thunks whose evaluators are the types of this module.
+//! The combinators on `SharedThunk` only borrow their thunks and
+//! return `SharedLazy` values.
+
+use crate::{Evaluate, SharedLazy, SharedThunk, Thunk};
+
+/// Evaluator of `Thunk::map`.
+pub struct Map<T, F>(T, F);
+
+/// Evaluator of `Thunk::zip`.
+pub struct Zip<T1, T2>(T1, T2);
+
+/// Evaluator of `Thunk::and_then`.
+pub struct AndThen<T, F>(T, F);
+
+/// Evaluator of `Thunk::flatten`.
+pub struct Flatten<T>(T);
+
+impl<E, V, U, F> Evaluate<U> for Map<Thunk<E, V>, F>
+where
+    E: Evaluate<V>,
+    F: FnOnce(V) -> U,
+{
+    fn evaluate(self) -> U {
+        (self.1)(self.0.into_value())
+    }
+}
+
+impl<E1, V1, E2, V2> Evaluate<(V1, V2)> for Zip<Thunk<E1, V1>, Thunk<E2, V2>>
+where
+    E1: Evaluate<V1>,
+    E2: Evaluate<V2>,
+{
+    fn evaluate(self) -> (V1, V2) {
+        (self.0.into_value(), self.1.into_value())
+    }
+}
+
+impl<E1, V, E2, U, F> Evaluate<U> for AndThen<Thunk<E1, V>, F>
+where
+    E1: Evaluate<V>,
+    E2: Evaluate<U>,
+    F: FnOnce(V) -> Thunk<E2, U>,
+{
+    fn evaluate(self) -> U {
+        (self.1)(self.0.into_value()).into_value()
+    }
+}
+
+impl<E1, E2, U> Evaluate<U> for Flatten<Thunk<E1, Thunk<E2, U>>>
+where
+    E1: Evaluate<Thunk<E2, U>>,
+    E2: Evaluate<U>,
+{
+    fn evaluate(self) -> U {
+        self.0.into_value().into_value()
+    }
+}
+
+impl<E: Evaluate<V>, V> Thunk<E, V> {
+    /// Return a thunk that applies a function to the value of this thunk.
+    ///
+    /// Neither thunk is evaluated before the returned thunk is forced.
+    ///
+    /// ~~~
+    /// # use lazy_mt::lazy;
+    /// let x = lazy!(vec![1, 2, 3]).map(|v| v.len());
+    /// assert!(!x.is_evaluated());
+    /// assert_eq!(*x, 3);
+    /// ~~~
+    pub fn map<U, F: FnOnce(V) -> U>(self, f: F) -> Thunk<Map<Self, F>, U> {
+        Thunk::new(Map(self, f))
+    }
+
+    /// Return a thunk that pairs the values of this and another thunk.
+    ///
+    /// ~~~
+    /// # use lazy_mt::lazy;
+    /// let x = lazy!(1).zip(lazy!("one"));
+    /// assert_eq!(*x, (1, "one"));
+    /// ~~~
+    pub fn zip<E2: Evaluate<V2>, V2>(
+        self,
+        other: Thunk<E2, V2>,
+    ) -> Thunk<Zip<Self, Thunk<E2, V2>>, (V, V2)> {
+        Thunk::new(Zip(self, other))
+    }
+
+    /// Return a thunk that applies a function returning a thunk
+    /// to the value of this thunk, and then evaluates the returned thunk.
+    ///
+    /// ~~~
+    /// # use lazy_mt::{lazy, Thunk};
+    /// let x = lazy!(2).and_then(|n| Thunk::new(move || n * 21));
+    /// assert_eq!(*x, 42);
+    /// ~~~
+    pub fn and_then<E2, U, F>(self, f: F) -> Thunk<AndThen<Self, F>, U>
+    where
+        E2: Evaluate<U>,
+        F: FnOnce(V) -> Thunk<E2, U>,
+    {
+        Thunk::new(AndThen(self, f))
+    }
+}
+
+impl<E1, E2, U> Thunk<E1, Thunk<E2, U>>
+where
+    E1: Evaluate<Thunk<E2, U>>,
+    E2: Evaluate<U>,
+{
+    /// Return a thunk that evaluates the thunk produced by this thunk.
+    ///
+    /// ~~~
+    /// # use lazy_mt::{lazy, Thunk};
+    /// let x = Thunk::new(|| lazy!(1)).flatten();
+    /// assert_eq!(*x, 1);
+    /// ~~~
+    pub fn flatten(self) -> Thunk<Flatten<Self>, U> {
+        Thunk::new(Flatten(self))
+    }
+}
+
+impl<E, V> SharedThunk<E, V>
+where
+    E: Evaluate<V> + Send + 'static,
+    V: Send + Sync + 'static,
+{
+    /// Return a shared thunk that applies a function to
+    /// the value of this thunk.
+    ///
+    /// Neither thunk is evaluated before the returned thunk is forced,
+    /// and this thunk remains usable.
+    ///
+    /// ~~~
+    /// # use lazy_mt::SharedThunk;
+    /// let x = SharedThunk::new(|| vec![1, 2, 3]);
+    /// let len = x.map(|v| v.len());
+    /// assert_eq!(*len, 3);
+    /// assert_eq!(*x, vec![1, 2, 3]);
+    /// ~~~
+    pub fn map<U, F>(&self, f: F) -> SharedLazy<U>
+    where
+        U: Send + Sync + 'static,
+        F: FnOnce(&V) -> U + Send + 'static,
+    {
+        let this = self.clone();
+        SharedThunk::new(Box::new(move || f(&this)))
+    }
+
+    /// Return a shared thunk that pairs
+    /// clones of the values of this and another thunk.
+    pub fn zip<E2, U>(&self, other: &SharedThunk<E2, U>) -> SharedLazy<(V, U)>
+    where
+        V: Clone,
+        E2: Evaluate<U> + Send + 'static,
+        U: Clone + Send + Sync + 'static,
+    {
+        let other = other.clone();
+        self.map(move |x| (x.clone(), (*other).clone()))
+    }
+
+    /// Return a shared thunk that applies a function returning a shared thunk
+    /// to the value of this thunk, and then evaluates the returned thunk.
+    ///
+    /// The value of the returned thunk is cloned
+    /// unless there are no other handles to it.
+    ///
+    /// ~~~
+    /// # use lazy_mt::SharedThunk;
+    /// let x = SharedThunk::new(|| 2);
+    /// let y = x.and_then(|n| { let n = *n; SharedThunk::new(move || n * 21) });
+    /// assert_eq!(*y, 42);
+    /// ~~~
+    pub fn and_then<E2, U, F>(&self, f: F) -> SharedLazy<U>
+    where
+        E2: Evaluate<U>,
+        U: Clone + Send + Sync + 'static,
+        F: FnOnce(&V) -> SharedThunk<E2, U> + Send + 'static,
+    {
+        self.map(|x| unwrap_or_clone(f(x)))
+    }
+}
+
+impl<E1, E2, U> SharedThunk<E1, SharedThunk<E2, U>>
+where
+    E1: Evaluate<SharedThunk<E2, U>> + Send + 'static,
+    E2: Evaluate<U> + Send + 'static,
+    U: Clone + Send + Sync + 'static,
+{
+    /// Return a shared thunk that evaluates the thunk produced by this thunk.
+    ///
+    /// The value of the produced thunk is cloned,
+    /// because this thunk keeps its handle to the produced thunk.
+    pub fn flatten(&self) -> SharedLazy<U> {
+        self.map(|x| (**x).clone())
+    }
+}
+
+fn unwrap_or_clone<E: Evaluate<V>, V: Clone + Send + Sync>(thunk: SharedThunk<E, V>) -> V {
+    SharedThunk::try_unwrap(thunk).unwrap_or_else(|thunk| (*thunk).clone())
+}
@@ -0,0 +1,208 @@
+//! Detection of evaluation cycles.
+//!
+//! A cycle arises when a thread forces a thunk that
+//! it is already evaluating itself (re-entrant forcing), or
+//! when several threads wait for thunks that are
+//! being evaluated by each other or by threads that they spawned.
+//! Without detection, both situations deadlock.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::state::{EVALUATING, OWNER_SHIFT, TAG};
+
+/// An identifier of a thunk, derived from its address.
+///
+/// The identifier of a thunk is only stable as long as the thunk is not moved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ThunkId(pub(crate) usize);
+
+impl fmt::Display for ThunkId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+/// An error signalling that a thunk depends on itself.
+///
+/// This corresponds to Haskell's `<<loop>>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CycleError {
+    thunks: Vec<ThunkId>,
+}
+
+impl CycleError {
+    /// Return the thunks that form the cycle,
+    /// starting with the thunk whose forcing closed the cycle.
+    pub fn thunks(&self) -> &[ThunkId] {
+        &self.thunks
+    }
+}
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<<loop>>: cycle between thunks")?;
+        for id in &self.thunks {
+            write!(f, " {}", id)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CycleError {}
+
+static NEXT_THREAD: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+    static THREAD: usize = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+    /// Thunks that the current thread is evaluating, innermost last.
+    static STACK: RefCell<Vec<ThunkId>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Return a nonzero identifier of the current thread.
+pub(crate) fn thread() -> usize {
+    THREAD.with(|t| *t)
+}
+
+/// Marks a thunk as being evaluated by the current thread while alive.
+pub(crate) struct Evaluation;
+
+impl Evaluation {
+    pub fn new(id: ThunkId) -> Self {
+        STACK.with(|s| s.borrow_mut().push(id));
+        Evaluation
+    }
+}
+
+impl Drop for Evaluation {
+    fn drop(&mut self) {
+        STACK.with(|s| s.borrow_mut().pop());
+    }
+}
+
+/// Return the cycle closed by the current thread forcing a thunk
+/// that it is already evaluating.
+pub(crate) fn reentrant(id: ThunkId) -> CycleError {
+    STACK.with(|s| {
+        let s = s.borrow();
+        let start = s.iter().rposition(|i| *i == id).unwrap_or(0);
+        CycleError {
+            thunks: s[start..].to_vec(),
+        }
+    })
+}
+
+/// A thread waiting for the evaluation of a thunk.
+struct Wait {
+    thread: usize,
+    thunk: ThunkId,
+    state: *const AtomicUsize,
+}
+
+/// A thread waiting for a thread that it spawned to finish.
+struct Join {
+    parent: usize,
+    child: usize,
+}
+
+/// What threads are waiting for.
+struct Waits {
+    thunks: Vec<Wait>,
+    joins: Vec<Join>,
+}
+
+// The pointer in a `Wait` is only dereferenced while holding `WAITS`,
+// and the waiting thread removes its `Wait` before it stops borrowing the thunk.
+unsafe impl Send for Wait {}
+
+static WAITS: Mutex<Waits> = Mutex::new(Waits {
+    thunks: Vec::new(),
+    joins: Vec::new(),
+});
+
+/// Return the thread evaluating a thunk with the given state word, if any.
+fn owner(state: &AtomicUsize) -> Option<usize> {
+    let s = state.load(Ordering::Acquire);
+    (s & TAG == EVALUATING).then_some(s >> OWNER_SHIFT)
+}
+
+/// Registers the current thread as waiting for a thunk while alive.
+pub(crate) struct Waiting(usize);
+
+impl Waiting {
+    /// Register the current thread as waiting for a thunk,
+    /// unless this would close a cycle of waiting threads.
+    ///
+    /// `state` is the state word of the thunk,
+    /// which includes the thread evaluating it.
+    pub fn new(thunk: ThunkId, state: &AtomicUsize) -> Result<Self, CycleError> {
+        let me = thread();
+        let mut waits = WAITS.lock().unwrap();
+
+        // Search the threads that the evaluating thread waits for,
+        // directly or indirectly, together with the thunks that lead to them.
+        let mut todo: Vec<_> = owner(state).map(|t| (t, vec![thunk])).into_iter().collect();
+        let mut seen = Vec::new();
+        while let Some((t, thunks)) = todo.pop() {
+            if t == me {
+                return Err(CycleError { thunks });
+            }
+            if seen.contains(&t) {
+                continue;
+            }
+            seen.push(t);
+            if let Some(w) = waits.thunks.iter().find(|w| w.thread == t) {
+                // Safe because the `Wait` cannot be removed while we hold `WAITS`.
+                if let Some(owner) = owner(unsafe { &*w.state }) {
+                    let mut thunks = thunks.clone();
+                    thunks.push(w.thunk);
+                    todo.push((owner, thunks))
+                }
+            }
+            let children = waits.joins.iter().filter(|j| j.parent == t);
+            todo.extend(children.map(|j| (j.child, thunks.clone())))
+        }
+
+        waits.thunks.push(Wait {
+            thread: me,
+            thunk,
+            state,
+        });
+        Ok(Waiting(me))
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        let mut waits = WAITS.lock().unwrap();
+        if let Some(i) = waits.thunks.iter().position(|w| w.thread == self.0) {
+            waits.thunks.swap_remove(i);
+        }
+    }
+}
+
+/// Registers the current thread as spawned by a thread
+/// that waits for it to finish while alive.
+///
+/// This allows detecting cycles where the current thread
+/// forces a thunk that the waiting thread is evaluating.
+pub(crate) struct Joined(usize);
+
+impl Joined {
+    pub fn new(parent: usize) -> Self {
+        let child = thread();
+        WAITS.lock().unwrap().joins.push(Join { parent, child });
+        Joined(child)
+    }
+}
+
+impl Drop for Joined {
+    fn drop(&mut self) {
+        let mut waits = WAITS.lock().unwrap();
+        if let Some(i) = waits.joins.iter().position(|j| j.child == self.0) {
+            waits.joins.swap_remove(i);
+        }
+    }
+}
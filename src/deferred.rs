@@ -0,0 +1,90 @@
+//! Iterative dropping of deeply nested values.
+//!
+//! Dropping a list cell drops its tail, and so on.
+//! For long lists, this would recurse deeply.
+//! Instead, the outermost drop on a thread
+//! collects the values to be dropped and drops them in a loop.
+//! Because values may be dropped after the outermost drop
+//! of their enclosing value has started,
+//! only values of `'static` types may be deferred.
+
+use std::cell::RefCell;
+use std::mem;
+use std::sync::Arc;
+
+thread_local! {
+    /// Values to be dropped, if a value is currently being dropped on this thread.
+    static DEFERRED: RefCell<Option<Vec<Deferred>>> = const { RefCell::new(None) };
+}
+
+/// A value to be dropped, with its type erased.
+pub(crate) struct Deferred {
+    ptr: *const (),
+    drop: unsafe fn(*const ()),
+}
+
+impl Deferred {
+    pub fn from_arc<T: 'static>(arc: Arc<T>) -> Self {
+        unsafe fn drop_arc<T>(ptr: *const ()) {
+            mem::drop(Arc::from_raw(ptr as *const T))
+        }
+        Deferred {
+            ptr: Arc::into_raw(arc) as *const (),
+            drop: drop_arc::<T>,
+        }
+    }
+}
+
+impl Drop for Deferred {
+    fn drop(&mut self) {
+        // Safe because `ptr` was obtained from `Arc::into_raw`
+        // for the type that `drop` was instantiated with.
+        unsafe { (self.drop)(self.ptr) }
+    }
+}
+
+/// Drop a value, or, if a value is currently being dropped on this thread,
+/// defer it until that drop has finished.
+pub(crate) fn drop<T>(val: T, defer: impl FnOnce(T) -> Deferred) {
+    // If the thread-local storage is already destroyed,
+    // this drops the value directly.
+    let outermost = DEFERRED.try_with(|deferred| {
+        let mut deferred = deferred.borrow_mut();
+        let outermost = deferred.is_none();
+        deferred.get_or_insert_with(Vec::new);
+        outermost
+    });
+    match outermost {
+        Ok(true) => {
+            let guard = Guard;
+            mem::drop(val);
+            mem::forget(guard);
+            drop_deferred()
+        }
+        Ok(false) => {
+            let val = defer(val);
+            DEFERRED.with(|deferred| deferred.borrow_mut().as_mut().unwrap().push(val))
+        }
+        Err(_) => mem::drop(val),
+    }
+}
+
+/// If dropping a value panics, drop the remaining values nonetheless.
+struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        drop_deferred()
+    }
+}
+
+/// Drop all deferred values.
+fn drop_deferred() {
+    let guard = Guard;
+    let pop = |deferred: &RefCell<Option<Vec<_>>>| deferred.borrow_mut().as_mut()?.pop();
+    while let Some(val) = DEFERRED.with(pop) {
+        mem::drop(val)
+    }
+    mem::forget(guard);
+    DEFERRED.with(|deferred| *deferred.borrow_mut() = None)
+}
@@ -0,0 +1,105 @@
+//! Errors that can occur when forcing thunks.
+
+use std::any::Any;
+use std::fmt;
+use std::panic;
+
+use crate::CycleError;
+
+/// The reason why a thunk could not be forced.
+#[derive(Clone, Debug)]
+pub enum ForceError {
+    /// The evaluation of the thunk panicked.
+    Poisoned(Panic),
+    /// The thunk depends on itself.
+    Cycle(CycleError),
+    /// Another thread did not finish evaluating the thunk in time.
+    TimedOut,
+}
+
+impl ForceError {
+    /// Panic with this error.
+    ///
+    /// For a poisoned thunk, this resumes the panic of its evaluation.
+    /// For a cycle, this panics with the `CycleError` as payload.
+    pub(crate) fn raise(self) -> ! {
+        match self {
+            ForceError::Poisoned(p) => p.resume(),
+            ForceError::Cycle(c) => panic::panic_any(c),
+            ForceError::TimedOut => panic!("{}", self),
+        }
+    }
+}
+
+impl fmt::Display for ForceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ForceError::Poisoned(p) => p.fmt(f),
+            ForceError::Cycle(c) => c.fmt(f),
+            ForceError::TimedOut => write!(f, "timed out waiting for thunk evaluation"),
+        }
+    }
+}
+
+impl std::error::Error for ForceError {}
+
+/// A panic that occurred during the evaluation of a thunk.
+///
+/// Panic payloads cannot be cloned in general,
+/// so only payloads of type `&'static str` and `String`
+/// (as produced by `panic!`) as well as `CycleError` are preserved.
+#[derive(Clone, Debug)]
+pub struct Panic(Payload);
+
+#[derive(Clone, Debug)]
+enum Payload {
+    Str(&'static str),
+    String(String),
+    Cycle(CycleError),
+    Other,
+}
+
+impl Panic {
+    pub(crate) fn new(payload: &(dyn Any + Send)) -> Self {
+        if let Some(s) = payload.downcast_ref::<&'static str>() {
+            Panic(Payload::Str(s))
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            Panic(Payload::String(s.clone()))
+        } else if let Some(c) = payload.downcast_ref::<CycleError>() {
+            Panic(Payload::Cycle(c.clone()))
+        } else {
+            Panic(Payload::Other)
+        }
+    }
+
+    /// Return the panic message, if the payload was a string.
+    pub fn message(&self) -> Option<&str> {
+        match &self.0 {
+            Payload::Str(s) => Some(s),
+            Payload::String(s) => Some(s),
+            Payload::Cycle(_) | Payload::Other => None,
+        }
+    }
+
+    /// Resume the panic with (a copy of) its original payload.
+    pub fn resume(self) -> ! {
+        match self.0 {
+            Payload::Str(s) => panic::resume_unwind(Box::new(s)),
+            Payload::String(s) => panic::resume_unwind(Box::new(s)),
+            Payload::Cycle(c) => panic::resume_unwind(Box::new(c)),
+            Payload::Other => panic::resume_unwind(Box::new("thunk evaluation panicked")),
+        }
+    }
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            Payload::Cycle(c) => write!(f, "thunk evaluation panicked: {}", c),
+            _ => match self.message() {
+                Some(msg) => write!(f, "thunk evaluation panicked: {}", msg),
+                None => write!(f, "thunk evaluation panicked"),
+            },
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Incremental recomputation of derived values.
+//!
+//! Every change of an input starts a new global revision.
+//! A derived value remembers the inputs and derived values that
+//! it read during its computation, as well as
+//! the revision in which it was last verified to be up to date.
+//! When it is accessed in a later revision,
+//! it is only recomputed if one of its dependencies changed since then.
+//! If the recomputation yields a value equal to the previous one,
+//! the values derived from it are not recomputed ("early cutoff").
+
+use std::cell::RefCell;
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::{Lazy, Thunk, ThunkState};
+
+static REVISION: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// Dependencies read by the derived values that
+    /// the current thread is computing, innermost last.
+    static READS: RefCell<Vec<Vec<Dep>>> = const { RefCell::new(Vec::new()) };
+}
+
+type Dep = Arc<dyn Dependency>;
+
+/// A value that a derived value may depend on.
+trait Dependency: Send + Sync {
+    /// Return true if the value changed after the given revision.
+    fn changed_after(&self, rev: u64) -> bool;
+}
+
+/// Record a dependency of the derived value that
+/// the current thread is computing, if any.
+fn record(dep: impl FnOnce() -> Dep) {
+    READS.with(|reads| {
+        if let Some(deps) = reads.borrow_mut().last_mut() {
+            deps.push(dep())
+        }
+    })
+}
+
+/// Records the dependencies of a computation while alive.
+struct Reads;
+
+impl Reads {
+    fn start() -> Self {
+        READS.with(|reads| reads.borrow_mut().push(Vec::new()));
+        Reads
+    }
+
+    fn finish(self) -> Vec<Dep> {
+        mem::forget(self);
+        let mut deps = READS.with(|reads| reads.borrow_mut().pop().unwrap());
+        deps.sort_by_key(|dep| Arc::as_ptr(dep) as *const () as usize);
+        deps.dedup_by(|a, b| Arc::ptr_eq(a, b));
+        deps
+    }
+}
+
+impl Drop for Reads {
+    fn drop(&mut self) {
+        READS.with(|reads| reads.borrow_mut().pop());
+    }
+}
+
+/// An input value that can be changed.
+///
+/// ~~~
+/// # use lazy_mt::{Derived, Input};
+/// let a = Input::new(1);
+/// let b = Input::new(2);
+/// let sum = {
+///     let (a, b) = (a.clone(), b.clone());
+///     Derived::new(move || a.get() + b.get())
+/// };
+/// assert_eq!(sum.get(), 3);
+/// a.set(10);
+/// assert_eq!(sum.get(), 12);
+/// ~~~
+pub struct Input<T>(Arc<InputNode<T>>);
+
+struct InputNode<T> {
+    value: RwLock<T>,
+    changed_at: AtomicU64,
+}
+
+impl<T> Clone for Input<T> {
+    fn clone(&self) -> Self {
+        Input(self.0.clone())
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Input<T> {
+    /// Create an input with an initial value.
+    pub fn new(val: T) -> Self {
+        Input(Arc::new(InputNode {
+            value: RwLock::new(val),
+            changed_at: AtomicU64::new(REVISION.load(Ordering::SeqCst)),
+        }))
+    }
+
+    /// Return the value of the input.
+    ///
+    /// If this is called during the computation of a derived value,
+    /// the derived value is recomputed when the input changes.
+    pub fn get(&self) -> T {
+        record(|| self.0.clone());
+        self.0.value.read().unwrap().clone()
+    }
+
+    /// Change the value of the input, starting a new revision.
+    ///
+    /// Derived values that are being computed while the input changes
+    /// may have read either value, but
+    /// they are recomputed on their next access in any case.
+    pub fn set(&self, val: T) {
+        let mut value = self.0.value.write().unwrap();
+        *value = val;
+        // This happens while holding the lock, so that
+        // computations that read the new value also see the new revision.
+        let rev = REVISION.fetch_add(1, Ordering::SeqCst) + 1;
+        self.0.changed_at.store(rev, Ordering::SeqCst);
+    }
+}
+
+impl<T: Send + Sync> Dependency for InputNode<T> {
+    fn changed_after(&self, rev: u64) -> bool {
+        self.changed_at.load(Ordering::SeqCst) > rev
+    }
+}
+
+/// A value derived from inputs and other derived values,
+/// which is recomputed only when these change.
+///
+/// Every derived value is computed at most once per revision,
+/// even if multiple threads access it at the same time.
+/// A derived value that reads itself yields a `CycleError`
+/// like a thunk that depends on itself.
+///
+/// ~~~
+/// # use lazy_mt::{Derived, Input};
+/// # use std::sync::atomic::{AtomicUsize, Ordering};
+/// static RUNS: AtomicUsize = AtomicUsize::new(0);
+/// let n = Input::new(2);
+/// let parity = {
+///     let n = n.clone();
+///     Derived::new(move || n.get() % 2)
+/// };
+/// let label = {
+///     let parity = parity.clone();
+///     Derived::new(move || {
+///         RUNS.fetch_add(1, Ordering::Relaxed);
+///         if parity.get() == 0 { "even" } else { "odd" }
+///     })
+/// };
+/// assert_eq!(label.get(), "even");
+///
+/// // The parity is recomputed, but is equal to the previous one,
+/// // so the label is not recomputed.
+/// n.set(4);
+/// assert_eq!(label.get(), "even");
+/// assert_eq!(RUNS.load(Ordering::Relaxed), 1);
+///
+/// n.set(5);
+/// assert_eq!(label.get(), "odd");
+/// assert_eq!(RUNS.load(Ordering::Relaxed), 2);
+/// ~~~
+pub struct Derived<T>(Arc<DerivedNode<T>>);
+
+struct DerivedNode<T> {
+    f: Arc<dyn Fn() -> T + Send + Sync>,
+    /// The latest computation, with the revision in which it was created.
+    memo: Mutex<(u64, Arc<Lazy<Memo<T>>>)>,
+}
+
+/// The result of a computation.
+struct Memo<T> {
+    value: T,
+    deps: Vec<Dep>,
+    /// The revision in which the value last changed.
+    changed_at: u64,
+    /// The last revision in which the value was known to be up to date.
+    verified_at: AtomicU64,
+}
+
+impl<T> Clone for Derived<T> {
+    fn clone(&self) -> Self {
+        Derived(self.0.clone())
+    }
+}
+
+impl<T: Clone + PartialEq + Send + Sync + 'static> Derived<T> {
+    /// Create a derived value that is lazily computed by `f`.
+    pub fn new(f: impl Fn() -> T + Send + Sync + 'static) -> Self {
+        let f: Arc<dyn Fn() -> T + Send + Sync> = Arc::new(f);
+        let rev = REVISION.load(Ordering::SeqCst);
+        let memo = Arc::new(compute(f.clone(), None));
+        Derived(Arc::new(DerivedNode {
+            f,
+            memo: Mutex::new((rev, memo)),
+        }))
+    }
+
+    /// Return the value, recomputing it if
+    /// one of its dependencies changed since its last computation.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `Thunk::force` if
+    /// the computation panicked or the value depends on itself.
+    /// A computation that panicked is retried in a later revision.
+    pub fn get(&self) -> T {
+        let memo = self.0.refresh();
+        record(|| self.0.clone());
+        memo.value.clone()
+    }
+}
+
+impl<T: Clone + PartialEq + Send + Sync + 'static> DerivedNode<T> {
+    /// Return a computation that is up to date with the current revision.
+    fn refresh(&self) -> Arc<Lazy<Memo<T>>> {
+        loop {
+            let rev = REVISION.load(Ordering::SeqCst);
+            let (created_at, thunk) = self.memo.lock().unwrap().clone();
+            if thunk.state() == ThunkState::Poisoned && created_at < rev {
+                self.replace(&thunk, rev, None);
+                continue;
+            }
+            let memo: &Memo<T> = &thunk;
+            let verified_at = memo.verified_at.load(Ordering::SeqCst);
+            let changed = |dep: &Dep| dep.changed_after(verified_at);
+            if verified_at >= rev || !memo.deps.iter().any(changed) {
+                memo.verified_at.fetch_max(rev, Ordering::SeqCst);
+                return thunk;
+            }
+            self.replace(&thunk, rev, Some(thunk.clone()));
+        }
+    }
+
+    /// Replace the current computation by a new one,
+    /// unless another thread did so already.
+    fn replace(&self, current: &Arc<Lazy<Memo<T>>>, rev: u64, old: Option<Arc<Lazy<Memo<T>>>>) {
+        let mut memo = self.memo.lock().unwrap();
+        if Arc::ptr_eq(&memo.1, current) {
+            *memo = (rev, Arc::new(compute(self.f.clone(), old)))
+        }
+    }
+}
+
+/// Lazily compute a value,
+/// reusing the revision of the old value if the new value is equal to it.
+fn compute<T>(f: Arc<dyn Fn() -> T + Send + Sync>, old: Option<Arc<Lazy<Memo<T>>>>) -> Lazy<Memo<T>>
+where
+    T: PartialEq + Send + Sync + 'static,
+{
+    Thunk::new(Box::new(move || {
+        let rev = REVISION.load(Ordering::SeqCst);
+        let reads = Reads::start();
+        let value = f();
+        let deps = reads.finish();
+        let old = old.as_ref().and_then(|old| old.get());
+        let changed_at = match old {
+            Some(old) if old.value == value => old.changed_at,
+            _ => rev,
+        };
+        let verified_at = AtomicU64::new(rev);
+        Memo {
+            value,
+            deps,
+            changed_at,
+            verified_at,
+        }
+    }))
+}
+
+impl<T: Clone + PartialEq + Send + Sync + 'static> Dependency for DerivedNode<T> {
+    fn changed_after(&self, rev: u64) -> bool {
+        self.refresh().changed_at > rev
+    }
+}
@@ -0,0 +1,77 @@
+//! Parallel evaluation of several evaluators.
+
+use std::panic;
+use std::thread::{self, ScopedJoinHandle};
+
+use crate::{cycle, Evaluate};
+
+/// An evaluator that evaluates a tuple of evaluators in parallel,
+/// producing a tuple of their values.
+///
+/// Every component is evaluated on its own thread.
+/// This is usually constructed by the `join!` macro.
+///
+/// ~~~
+/// # use lazy_mt::{Join, Thunk};
+/// let x = Thunk::new(Join((|| 1 + 1, || "two".to_string())));
+/// assert_eq!(*x, (2, "two".to_string()));
+/// ~~~
+///
+/// # Panics
+///
+/// If the evaluation of any component panics,
+/// the panic is resumed once all components have finished.
+///
+/// A component that forces the thunk that is evaluating it
+/// panics with a `CycleError` instead of deadlocking:
+///
+/// ~~~
+/// # use lazy_mt::{CycleError, Join, Thunk};
+/// # use std::{panic, sync::{Arc, OnceLock}};
+/// type F = Box<dyn FnOnce() -> u32 + Send>;
+/// type Pair = Thunk<Join<(F, F)>, (u32, u32)>;
+/// let cell: Arc<OnceLock<Arc<Pair>>> = Arc::new(OnceLock::new());
+/// let c = cell.clone();
+/// let first: F = Box::new(|| 1);
+/// let second: F = Box::new(move || c.get().unwrap().0 + 1);
+/// let x = Arc::new(Thunk::new(Join((first, second))));
+/// cell.set(x.clone()).ok();
+///
+/// let err = panic::catch_unwind(|| x.force()).unwrap_err();
+/// let cycle = err.downcast_ref::<CycleError>().unwrap();
+/// assert_eq!(cycle.thunks(), &[x.id()]);
+/// ~~~
+pub struct Join<T>(pub T);
+
+fn join<V>(handle: ScopedJoinHandle<V>) -> V {
+    handle.join().unwrap_or_else(|p| panic::resume_unwind(p))
+}
+
+macro_rules! impl_join {
+    ($($E:ident $V:ident $x:ident),+) => {
+        impl<$($E, $V),+> Evaluate<($($V,)+)> for Join<($($E,)+)>
+        where
+            $($E: Evaluate<$V> + Send, $V: Send),+
+        {
+            fn evaluate(self) -> ($($V,)+) {
+                let ($($x,)+) = self.0;
+                let parent = cycle::thread();
+                thread::scope(|s| {
+                    let ($($x,)+) = ($(s.spawn(move || {
+                        let _joined = cycle::Joined::new(parent);
+                        $x.evaluate()
+                    }),)+);
+                    ($(join($x),)+)
+                })
+            }
+        }
+    };
+}
+
+impl_join!(E1 V1 x1, E2 V2 x2);
+impl_join!(E1 V1 x1, E2 V2 x2, E3 V3 x3);
+impl_join!(E1 V1 x1, E2 V2 x2, E3 V3 x3, E4 V4 x4);
+impl_join!(E1 V1 x1, E2 V2 x2, E3 V3 x3, E4 V4 x4, E5 V5 x5);
+impl_join!(E1 V1 x1, E2 V2 x2, E3 V3 x3, E4 V4 x4, E5 V5 x5, E6 V6 x6);
+impl_join!(E1 V1 x1, E2 V2 x2, E3 V3 x3, E4 V4 x4, E5 V5 x5, E6 V6 x6, E7 V7 x7);
+impl_join!(E1 V1 x1, E2 V2 x2, E3 V3 x3, E4 V4 x4, E5 V5 x5, E6 V6 x6, E7 V7 x7, E8 V8 x8);
@@ -3,19 +3,164 @@
 //! For an introduction to lazy evaluation,
 //! please see the documentation of the `lazy-st` crate.
 
+use std::cell::UnsafeCell;
+use std::fmt;
 use std::mem;
 use std::ops::{Deref, DerefMut};
-use std::sync::RwLock;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 pub use lazy_st::Evaluate;
 
+mod async_thunk;
+pub mod combinators;
+mod cycle;
+mod deferred;
+mod error;
+mod incremental;
+mod join;
+pub mod list;
+pub mod map;
+mod memo;
+mod par;
+mod resettable;
+#[cfg(feature = "serde")]
+pub mod serde;
+mod shared;
+mod state;
+mod try_thunk;
+pub mod vec;
+
+pub use async_thunk::{AsyncThunk, Force};
+pub use cycle::{CycleError, ThunkId};
+pub use error::{ForceError, Panic};
+pub use incremental::{Derived, Input};
+pub use join::Join;
+pub use list::LazyList;
+pub use map::LazyMap;
+pub use memo::{memoize, Memo};
+pub use par::{force_all, try_force_all};
+#[cfg(feature = "rayon")]
+pub use par::{par_force, par_try_force};
+pub use resettable::{ResettableThunk, Snapshot};
+pub use shared::{SharedLazy, SharedThunk};
+pub use state::ThunkState;
+pub use try_thunk::{ErrorPolicy, Failure, TryEvaluate, TryThunk};
+pub use vec::LazyVec;
+
+use state::{Claim, State, EVALUATED, UNEVALUATED};
+
 use self::Inner::{Evaluating, Unevaluated, Value};
 
 /// A lazily evaluated value.
-pub struct Thunk<E, V>(RwLock<Inner<E, V>>);
+///
+/// The evaluation state is tracked by an atomic state word,
+/// so that reading an already evaluated thunk
+/// does not take any lock.
+/// Threads only block while another thread is evaluating the thunk.
+///
+/// Forcing a thunk that depends on itself,
+/// either directly or via threads waiting for each other,
+/// yields a `CycleError` instead of deadlocking.
+///
+/// Long chains of thunks neither overflow the stack when they are forced
+/// nor when they are dropped, because the stack is grown on demand:
+///
+/// ~~~
+/// # use lazy_mt::{lazy, Lazy};
+/// let chain = |n| {
+///     let mut x: Lazy<u64> = lazy!(0);
+///     for _ in 0..n {
+///         let prev = x;
+///         x = lazy!(*prev + 1);
+///     }
+///     x
+/// };
+/// assert_eq!(*chain(200_000), 200_000);
+/// drop(chain(200_000));
+/// ~~~
+///
+/// # Thread safety
+///
+/// A thunk can be shared between threads if
+/// its evaluator can be sent to the thread that evaluates it and
+/// its value can be sent and shared between threads.
+/// In particular, the evaluator does not need to be `Sync`,
+/// because only a single thread ever accesses it:
+///
+/// ~~~
+/// # use lazy_mt::Thunk;
+/// # use std::cell::Cell;
+/// fn assert_sync<T: Sync>(_: &T) {}
+/// let c = Cell::new(1);
+/// assert_sync(&Thunk::new(move || c.get()));
+/// ~~~
+///
+/// However, the evaluator must be `Send`:
+///
+/// ~~~compile_fail
+/// # use lazy_mt::Thunk;
+/// # use std::rc::Rc;
+/// fn assert_sync<T: Sync>(_: &T) {}
+/// let rc = Rc::new(1);
+/// assert_sync(&Thunk::new(move || *rc));
+/// ~~~
+///
+/// The value must be `Sync`:
+///
+/// ~~~compile_fail
+/// # use lazy_mt::Thunk;
+/// # use std::cell::Cell;
+/// fn assert_sync<T: Sync>(_: &T) {}
+/// assert_sync(&Thunk::new(|| Cell::new(1)));
+/// ~~~
+///
+/// And the value must be `Send`, because
+/// it may be dropped by a different thread than the one that evaluated it:
+///
+/// ~~~compile_fail
+/// # use lazy_mt::Thunk;
+/// # use std::sync::MutexGuard;
+/// fn assert_sync<T: Sync>(_: &T) {}
+/// fn sync_not_send<'a>() -> MutexGuard<'a, ()> { unimplemented!() }
+/// assert_sync(&Thunk::new(sync_not_send));
+/// ~~~
+///
+/// Finally, a thunk can be sent to another thread
+/// if both its evaluator and its value are `Send`:
+///
+/// ~~~compile_fail
+/// # use lazy_mt::Thunk;
+/// # use std::rc::Rc;
+/// fn assert_send<T: Send>(_: T) {}
+/// assert_send(Thunk::new(|| Rc::new(1)));
+/// ~~~
+pub struct Thunk<E, V> {
+    state: State,
+    inner: UnsafeCell<Inner<E, V>>,
+    policy: PoisonPolicy,
+}
+
+// `inner` is only written by the thread that claimed the evaluation
+// or by `retry` while the thunk is poisoned,
+// and only read once the thunk is evaluated.
+// Therefore, the evaluator is only ever accessed by one thread at a time,
+// which may differ from the thread that created or drops the thunk, and
+// the value is shared between threads and may be dropped by another thread.
+// (`Send` is derived automatically and requires `E: Send` and `V: Send`.)
+unsafe impl<E: Send, V: Send + Sync> Sync for Thunk<E, V> {}
+
+// A panicking evaluation poisons the thunk,
+// so its broken state can never be observed.
+impl<E, V: RefUnwindSafe> RefUnwindSafe for Thunk<E, V> {}
 
 /// A lazily evaluated value produced from a closure.
-pub type Lazy<T> = Thunk<Box<dyn FnOnce() -> T>, T>;
+///
+/// The closure is required to be `Send`,
+/// so that lazy values can be shared between threads.
+pub type Lazy<T> = Thunk<Box<dyn FnOnce() -> T + Send>, T>;
 
 /// Construct a lazily evaluated value using a closure.
 ///
@@ -24,13 +169,53 @@ pub type Lazy<T> = Thunk<Box<dyn FnOnce() -> T>, T>;
 /// let val = lazy!(7);
 /// assert_eq!(*val, 7);
 /// ~~~
+///
+/// The resulting value has type `Lazy<T>`,
+/// which can be sent to other threads:
+///
+/// ~~~
+/// # use lazy_mt::{lazy, Lazy};
+/// # use std::{sync::Arc, thread};
+/// let val: Arc<Lazy<String>> = Arc::new(lazy!("Hello".to_string()));
+/// let val2 = val.clone();
+/// thread::spawn(move || assert_eq!(**val2, "Hello")).join().unwrap();
+/// ~~~
 #[macro_export]
 macro_rules! lazy {
-    ($e:expr) => {
-        $crate::Thunk::new(Box::new(move || $e))
+    ($e:expr) => {{
+        let thunk: $crate::Lazy<_> = $crate::Thunk::new(Box::new(move || $e));
+        thunk
+    }};
+}
+
+/// Construct a lazily evaluated tuple of values
+/// that are evaluated in parallel when the tuple is forced.
+///
+/// This accepts between two and eight expressions.
+///
+/// ~~~
+/// # use lazy_mt::join;
+/// fn fib(n: u64) -> u64 { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }
+/// let fibs = join!(fib(20), fib(21), fib(22));
+/// assert_eq!(*fibs, (6765, 10946, 17711));
+/// ~~~
+#[macro_export]
+macro_rules! join {
+    ($e1:expr, $($e:expr),+ $(,)?) => {
+        $crate::Thunk::new($crate::Join((move || $e1, $(move || $e,)+)))
     };
 }
 
+/// What happens to a thunk after its evaluation panicked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoisonPolicy {
+    /// The thunk stays poisoned forever.
+    Stay,
+    /// The thunk stays poisoned until it is given
+    /// a new evaluator via `Thunk::retry`.
+    Retry,
+}
+
 impl<E, V> Thunk<E, V>
 where
     E: Evaluate<V>,
@@ -56,7 +241,24 @@ where
     /// assert_eq!(**reff, 7);
     /// ~~~
     pub fn new(e: E) -> Thunk<E, V> {
-        Thunk(RwLock::new(Unevaluated(e)))
+        Self::with_poison_policy(e, PoisonPolicy::Stay)
+    }
+
+    /// Create a lazily evaluated value that behaves
+    /// according to the given policy if its evaluation panics.
+    ///
+    /// ~~~
+    /// # use lazy_mt::{Lazy, PoisonPolicy, Thunk};
+    /// # use std::panic;
+    /// let x: Lazy<u32> = Thunk::with_poison_policy(Box::new(|| panic!("oops")), PoisonPolicy::Retry);
+    /// assert!(panic::catch_unwind(|| x.force()).is_err());
+    /// assert!(x.try_force().is_err());
+    ///
+    /// assert!(x.retry(Box::new(|| 1)).is_ok());
+    /// assert_eq!(*x, 1);
+    /// ~~~
+    pub fn with_poison_policy(e: E, policy: PoisonPolicy) -> Thunk<E, V> {
+        Self::with_state(UNEVALUATED, Unevaluated(e), policy)
     }
 
     /// Create a new, evaluated, thunk from a value.
@@ -67,23 +269,331 @@ where
     /// assert_eq!(*x, 10);
     /// ~~~
     pub fn evaluated(val: V) -> Thunk<E, V> {
-        Thunk(RwLock::new(Value(val)))
+        Self::with_state(EVALUATED, Value(val), PoisonPolicy::Stay)
+    }
+
+    fn with_state(tag: usize, inner: Inner<E, V>, policy: PoisonPolicy) -> Thunk<E, V> {
+        Thunk {
+            state: State::new(tag),
+            inner: UnsafeCell::new(inner),
+            policy,
+        }
     }
 
     /// Force evaluation of a thunk.
+    ///
+    /// If another thread is currently evaluating the thunk,
+    /// this blocks until that evaluation has finished.
+    ///
+    /// # Panics
+    ///
+    /// If the evaluation of the thunk panicked,
+    /// this resumes that panic with its original payload.
+    ///
+    /// ~~~
+    /// # use lazy_mt::Thunk;
+    /// # use std::{panic, sync::Arc, thread};
+    /// let x = Arc::new(Thunk::new(|| -> u32 { panic!("oops") }));
+    /// let y = x.clone();
+    /// assert!(thread::spawn(move || y.force()).join().is_err());
+    ///
+    /// let err = panic::catch_unwind(|| x.force()).unwrap_err();
+    /// assert_eq!(err.downcast_ref::<&str>(), Some(&"oops"));
+    /// ~~~
+    ///
+    /// If the thunk depends on itself,
+    /// this panics with a `CycleError` as payload.
+    ///
+    /// ~~~
+    /// # use lazy_mt::{lazy, CycleError, Lazy};
+    /// # use std::{panic, sync::{Arc, OnceLock}};
+    /// let cell: Arc<OnceLock<Arc<Lazy<u32>>>> = Arc::new(OnceLock::new());
+    /// let c = cell.clone();
+    /// let x: Arc<Lazy<u32>> = Arc::new(lazy!(1 + ***c.get().unwrap()));
+    /// cell.set(x.clone()).ok();
+    ///
+    /// let err = panic::catch_unwind(|| x.force()).unwrap_err();
+    /// let cycle = err.downcast_ref::<CycleError>().unwrap();
+    /// assert_eq!(cycle.thunks(), &[x.id()]);
+    /// ~~~
     pub fn force(&self) {
-        if let Value(_) = *self.0.read().unwrap() {
-            return;
-        };
+        if let Err(e) = self.try_force() {
+            e.raise()
+        }
+    }
 
-        let mut w = self.0.write().unwrap();
-        // We are the thread responsible for doing the evaluation.
-        match mem::replace(&mut *w, Evaluating) {
-            Unevaluated(e) => *w = Value(e.evaluate()),
-            Value(v) => *w = Value(v),
+    /// Force evaluation of a thunk, returning its value
+    /// or the reason why it could not be evaluated.
+    ///
+    /// ~~~
+    /// # use lazy_mt::{ForceError, Thunk};
+    /// # use std::panic;
+    /// let x = Thunk::new(|| -> u32 { panic!("oops") });
+    /// assert!(panic::catch_unwind(|| x.force()).is_err());
+    /// match x.try_force() {
+    ///     Err(ForceError::Poisoned(p)) => assert_eq!(p.message(), Some("oops")),
+    ///     _ => panic!(),
+    /// }
+    /// ~~~
+    pub fn try_force(&self) -> Result<&V, ForceError> {
+        if !self.state.is_evaluated() {
+            self.force_slow()?
+        }
+        // Safe because we just forced this thunk.
+        Ok(unsafe { self.get_unchecked() })
+    }
+
+    /// Force evaluation of a thunk, waiting at most for the given duration
+    /// if another thread is currently evaluating it.
+    ///
+    /// If the thunk is unevaluated, the current thread evaluates it,
+    /// regardless of how long this takes.
+    ///
+    /// ~~~
+    /// # use lazy_mt::{lazy, ForceError};
+    /// # use std::{sync::Arc, thread, time::Duration};
+    /// let x = Arc::new(lazy!({ thread::sleep(Duration::from_millis(500)); 1 }));
+    /// let y = x.clone();
+    /// let handle = thread::spawn(move || y.force());
+    /// while !x.is_evaluating() {}
+    ///
+    /// let timeout = Duration::from_millis(10);
+    /// assert!(matches!(x.force_timeout(timeout), Err(ForceError::TimedOut)));
+    /// assert_eq!(x.get_timeout(timeout), None);
+    ///
+    /// handle.join().unwrap();
+    /// assert_eq!(x.force_timeout(timeout).ok(), Some(&1));
+    /// ~~~
+    pub fn force_timeout(&self, timeout: Duration) -> Result<&V, ForceError> {
+        if !self.state.is_evaluated() {
+            self.force_until(Instant::now().checked_add(timeout))?
+        }
+        // Safe because we just forced this thunk.
+        Ok(unsafe { self.get_unchecked() })
+    }
+
+    /// Return the value of the thunk if it has been evaluated,
+    /// waiting at most for the given duration
+    /// if another thread is currently evaluating it.
+    ///
+    /// In contrast to `force_timeout`, this never starts an evaluation.
+    pub fn get_timeout(&self, timeout: Duration) -> Option<&V> {
+        if self.is_evaluating() {
+            let deadline = Instant::now().checked_add(timeout);
+            self.state.wait(self.id(), deadline).ok()?;
+        }
+        self.get()
+    }
+
+    /// Force evaluation of a thunk and return its value.
+    ///
+    /// ~~~
+    /// # use lazy_mt::lazy;
+    /// let x = lazy!(vec![1, 2, 3]);
+    /// assert_eq!(x.into_value(), vec![1, 2, 3]);
+    /// ~~~
+    pub fn into_value(mut self) -> V {
+        self.force();
+        match self.take() {
+            Value(val) => val,
+
+            // We just forced this thunk.
             _ => unreachable!(),
         }
     }
+
+    /// Return the value of a shared thunk if
+    /// there are no other references to it,
+    /// forcing its evaluation if necessary.
+    ///
+    /// Otherwise, the shared thunk is returned.
+    ///
+    /// ~~~
+    /// # use lazy_mt::{lazy, Thunk};
+    /// # use std::sync::Arc;
+    /// let x = Arc::new(lazy!(vec![1, 2, 3]));
+    /// let y = x.clone();
+    /// let x = Thunk::try_unwrap(x).unwrap_err();
+    /// drop(y);
+    /// assert_eq!(Thunk::try_unwrap(x).ok(), Some(vec![1, 2, 3]));
+    /// ~~~
+    pub fn try_unwrap(this: Arc<Self>) -> Result<V, Arc<Self>> {
+        Arc::try_unwrap(this).map(Self::into_value)
+    }
+
+    /// Return an identifier of the thunk,
+    /// as used by `CycleError`.
+    pub fn id(&self) -> ThunkId {
+        ThunkId(self as *const Self as usize)
+    }
+
+    /// Give a poisoned thunk a new evaluator,
+    /// such that the next access evaluates it again.
+    ///
+    /// This succeeds only if the thunk is poisoned and
+    /// was created with the `PoisonPolicy::Retry` policy;
+    /// otherwise, the evaluator is returned.
+    pub fn retry(&self, e: E) -> Result<(), E> {
+        if self.policy != PoisonPolicy::Retry {
+            return Err(e);
+        }
+        let mut e = Some(e);
+        // Safe because no thread accesses `inner` while the thunk is poisoned.
+        let reset = || unsafe { *self.inner.get() = Unevaluated(e.take().unwrap()) };
+        if self.state.unpoison(reset) {
+            Ok(())
+        } else {
+            Err(e.unwrap())
+        }
+    }
+
+    fn force_slow(&self) -> Result<(), ForceError> {
+        self.force_until(None)
+    }
+
+    fn force_until(&self, deadline: Option<Instant>) -> Result<(), ForceError> {
+        if let Claim::Evaluate = self.state.claim(self.id(), deadline)? {
+            // Safe because we claimed the evaluation,
+            // so no other thread accesses `inner` until we are done.
+            let inner = unsafe { &mut *self.inner.get() };
+            let e = match mem::replace(inner, Evaluating) {
+                Unevaluated(e) => e,
+                _ => unreachable!(),
+            };
+            *inner = Value(self.state.evaluate(self.id(), || e.evaluate()));
+            self.state.finish(EVALUATED);
+        }
+        Ok(())
+    }
+}
+
+impl<E, V> Thunk<E, V>
+where
+    E: Evaluate<V> + Send + 'static,
+    V: Send + Sync + 'static,
+{
+    /// Create a thunk and immediately start evaluating it
+    /// on a background thread.
+    ///
+    /// Forcing the thunk waits for the background evaluation to finish.
+    ///
+    /// ~~~
+    /// # use lazy_mt::Thunk;
+    /// let x = Thunk::spawn(|| (1..=100).sum::<u32>());
+    /// // ... do other work in the meantime ...
+    /// assert_eq!(**x, 5050);
+    /// ~~~
+    pub fn spawn(e: E) -> Arc<Self> {
+        let thunk = Arc::new(Self::new(e));
+        thunk.prefetch();
+        thunk
+    }
+
+    /// Start evaluating the thunk on a background thread
+    /// if it has not been evaluated yet, without waiting for the result.
+    ///
+    /// If the thunk is forced before the background thread
+    /// has started its evaluation, it is evaluated by the forcing thread.
+    /// In either case, the thunk is evaluated only once.
+    ///
+    /// ~~~
+    /// # use lazy_mt::lazy;
+    /// # use std::sync::Arc;
+    /// let x = Arc::new(lazy!(7));
+    /// x.prefetch();
+    /// assert_eq!(**x, 7);
+    /// ~~~
+    pub fn prefetch(self: &Arc<Self>) {
+        if self.state() == ThunkState::Unevaluated {
+            let thunk = self.clone();
+            thread::spawn(move || thunk.force());
+        }
+    }
+}
+
+impl<E, V> Thunk<E, V> {
+    /// Return the current evaluation state of the thunk,
+    /// without forcing it.
+    ///
+    /// ~~~
+    /// # use lazy_mt::{lazy, ThunkState};
+    /// let x = lazy!(1);
+    /// assert_eq!(x.state(), ThunkState::Unevaluated);
+    /// x.force();
+    /// assert_eq!(x.state(), ThunkState::Evaluated);
+    /// ~~~
+    pub fn state(&self) -> ThunkState {
+        self.state.get()
+    }
+
+    /// Return true if the thunk has been evaluated.
+    pub fn is_evaluated(&self) -> bool {
+        self.state.is_evaluated()
+    }
+
+    /// Return true if a thread is currently evaluating the thunk.
+    pub fn is_evaluating(&self) -> bool {
+        self.state() == ThunkState::Evaluating
+    }
+
+    /// Return the value of the thunk if it has been evaluated,
+    /// without forcing it.
+    ///
+    /// ~~~
+    /// # use lazy_mt::lazy;
+    /// let x = lazy!(1);
+    /// assert_eq!(x.get(), None);
+    /// x.force();
+    /// assert_eq!(x.get(), Some(&1));
+    /// ~~~
+    pub fn get(&self) -> Option<&V> {
+        if !self.state.is_evaluated() {
+            return None;
+        }
+        // Safe because we just checked that the thunk is evaluated.
+        Some(unsafe { self.get_unchecked() })
+    }
+
+    /// Return the value of the thunk without checking that it is evaluated.
+    ///
+    /// # Safety
+    ///
+    /// The thunk must be evaluated.
+    unsafe fn get_unchecked(&self) -> &V {
+        // Safe because the thunk is evaluated,
+        // so `inner` is never written to again while `self` is borrowed.
+        match &*self.inner.get() {
+            Value(val) => val,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Return the value of the thunk if it has been evaluated,
+    /// otherwise its evaluator, without forcing it.
+    ///
+    /// ~~~
+    /// # use lazy_mt::lazy;
+    /// let x = lazy!(1);
+    /// let e = x.into_inner().unwrap_err();
+    /// assert_eq!(e(), 1);
+    /// ~~~
+    ///
+    /// # Panics
+    ///
+    /// If the evaluation of the thunk panicked,
+    /// this resumes that panic, as the thunk has neither a value nor an evaluator.
+    pub fn into_inner(mut self) -> Result<V, E> {
+        match self.take() {
+            Value(val) => Ok(val),
+            Unevaluated(e) => Err(e),
+            Evaluating => self.state.panic().unwrap().resume(),
+        }
+    }
+
+    /// Take the evaluator or value out of the thunk.
+    fn take(&mut self) -> Inner<E, V> {
+        mem::replace(self.inner.get_mut(), Evaluating)
+    }
 }
 
 impl<E, V: Send + Sync> DerefMut for Thunk<E, V>
@@ -92,9 +602,8 @@ where
 {
     fn deref_mut(&mut self) -> &mut V {
         self.force();
-        match *self.0.write().unwrap() {
-            // Safe because getting this &'a mut T requires &'a mut self.
-            Value(ref mut val) => unsafe { mem::transmute(val) },
+        match self.inner.get_mut() {
+            Value(val) => val,
 
             // We just forced this thunk.
             _ => unreachable!(),
@@ -109,13 +618,63 @@ where
     type Target = V;
 
     fn deref(&self) -> &V {
-        self.force();
-        match *self.0.read().unwrap() {
-            // Safe because getting this &'a T requires &'a self.
-            Value(ref val) => unsafe { mem::transmute(val) },
+        match self.try_force() {
+            Ok(val) => val,
+            Err(e) => e.raise(),
+        }
+    }
+}
 
-            // We just forced this thunk.
-            _ => unreachable!(),
+/// Format the value of the thunk if it has been evaluated,
+/// without forcing it.
+///
+/// ~~~
+/// # use lazy_mt::lazy;
+/// let x = lazy!(1);
+/// assert_eq!(format!("{:?}", x), "Thunk(<unevaluated>)");
+/// x.force();
+/// assert_eq!(format!("{:?}", x), "Thunk(1)");
+/// ~~~
+impl<E, V: fmt::Debug> fmt::Debug for Thunk<E, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut tuple = f.debug_tuple("Thunk");
+        match self.state() {
+            ThunkState::Unevaluated => tuple.field(&format_args!("<unevaluated>")),
+            ThunkState::Evaluating => tuple.field(&format_args!("<evaluating>")),
+            ThunkState::Poisoned => tuple.field(&format_args!("<poisoned>")),
+            // Evaluated thunks stay evaluated.
+            ThunkState::Evaluated => tuple.field(self.get().unwrap()),
+        };
+        tuple.finish()
+    }
+}
+
+/// Force evaluation of the thunk and format its value.
+///
+/// ~~~
+/// # use lazy_mt::lazy;
+/// let x = lazy!(6 * 7);
+/// assert_eq!(x.to_string(), "42");
+/// ~~~
+///
+/// # Panics
+///
+/// Panics like `Thunk::force`.
+impl<E: Evaluate<V>, V: fmt::Display> fmt::Display for Thunk<E, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_force() {
+            Ok(val) => val.fmt(f),
+            Err(e) => e.raise(),
+        }
+    }
+}
+
+// The evaluator or value of a thunk may contain further thunks,
+// so dropping long chains of thunks recurses deeply.
+impl<E, V> Drop for Thunk<E, V> {
+    fn drop(&mut self) {
+        if mem::needs_drop::<Inner<E, V>>() {
+            state::grow_stack(|| drop(self.take()))
         }
     }
 }
@@ -0,0 +1,323 @@
+//! Lazily evaluated, memoized lists.
+
+use std::mem::ManuallyDrop;
+use std::sync::Arc;
+
+use self::Node::{Cons, Nil};
+use crate::deferred::{self, Deferred};
+use crate::{Lazy, Thunk};
+
+/// A lazily evaluated, memoized list.
+///
+/// Every cell of the list is a shared thunk,
+/// so lists can be traversed by multiple threads concurrently,
+/// while every cell is computed only once.
+/// Cloning a list is cheap, because it only clones a handle to its first cell.
+///
+/// ~~~
+/// # use lazy_mt::LazyList;
+/// let nats = LazyList::iterate(0u64, |n| n + 1);
+/// let evens = nats.filter(|n| n % 2 == 0);
+/// let squares = evens.map(|n| n * n);
+/// let v: Vec<_> = squares.take(4).iter().copied().collect();
+/// assert_eq!(v, vec![0, 4, 16, 36]);
+/// ~~~
+///
+/// Multiple threads may traverse the same list:
+///
+/// ~~~
+/// # use lazy_mt::LazyList;
+/// # use std::thread;
+/// let fibs = LazyList::unfold((0u64, 1u64), |(a, b)| Some((a, (b, a + b))));
+/// let handles: Vec<_> = (0..4)
+///     .map(|_| {
+///         let fibs = fibs.clone();
+///         thread::spawn(move || fibs.iter().nth(50).copied())
+///     })
+///     .collect();
+/// for h in handles {
+///     assert_eq!(h.join().unwrap(), Some(12586269025));
+/// }
+/// ~~~
+///
+/// Long lists and long chains of combinators neither overflow the stack
+/// when they are forced nor when they are dropped:
+///
+/// ~~~
+/// # use lazy_mt::LazyList;
+/// let mut l = LazyList::iterate(0u64, |n| n + 1);
+/// for _ in 0..100_000 {
+///     l = l.map(|n| n + 1);
+/// }
+/// assert_eq!(l.head(), Some(&100_000));
+///
+/// let l = LazyList::iterate(0u64, |n| n + 1).take(1_000_000);
+/// assert_eq!(l.iter().count(), 1_000_000);
+/// drop(l);
+/// ~~~
+pub struct LazyList<T: 'static>(ManuallyDrop<Arc<Lazy<Node<T>>>>);
+
+enum Node<T: 'static> {
+    Nil,
+    Cons(T, LazyList<T>),
+}
+
+impl<T: 'static> Clone for LazyList<T> {
+    fn clone(&self) -> Self {
+        LazyList(self.0.clone())
+    }
+}
+
+impl<T: Send + Sync + 'static> LazyList<T> {
+    /// Create a list whose first cell is computed lazily by a closure,
+    /// which returns `None` for the empty list and
+    /// `Some((head, tail))` for a nonempty list.
+    pub fn new(f: impl FnOnce() -> Option<(T, Self)> + Send + 'static) -> Self {
+        let node = Thunk::new(Box::new(move || match f() {
+            Some((x, xs)) => Cons(x, xs),
+            None => Nil,
+        }) as Box<_>);
+        Self::from_cell(node)
+    }
+
+    fn from_cell(node: Lazy<Node<T>>) -> Self {
+        LazyList(ManuallyDrop::new(Arc::new(node)))
+    }
+
+    /// Create an empty list.
+    pub fn nil() -> Self {
+        Self::from_cell(Thunk::evaluated(Nil))
+    }
+
+    /// Create a list from its first element and the remaining list.
+    pub fn cons(x: T, xs: Self) -> Self {
+        Self::from_cell(Thunk::evaluated(Cons(x, xs)))
+    }
+
+    /// Create a list by repeatedly applying a function to a state,
+    /// until the function returns `None`.
+    ///
+    /// ~~~
+    /// # use lazy_mt::LazyList;
+    /// let l = LazyList::unfold(3, |n| if n > 0 { Some((n, n - 1)) } else { None });
+    /// assert_eq!(l.iter().copied().collect::<Vec<_>>(), vec![3, 2, 1]);
+    /// ~~~
+    pub fn unfold<S, F>(seed: S, f: F) -> Self
+    where
+        S: Send + 'static,
+        F: Fn(S) -> Option<(T, S)> + Send + Sync + 'static,
+    {
+        Self::unfold_shared(seed, Arc::new(f))
+    }
+
+    fn unfold_shared<S, F>(seed: S, f: Arc<F>) -> Self
+    where
+        S: Send + 'static,
+        F: Fn(S) -> Option<(T, S)> + Send + Sync + 'static,
+    {
+        Self::new(move || {
+            let (x, seed) = f(seed)?;
+            Some((x, Self::unfold_shared(seed, f)))
+        })
+    }
+
+    /// Create the infinite list `x, f(x), f(f(x)), ...`.
+    pub fn iterate<F>(x: T, f: F) -> Self
+    where
+        F: Fn(&T) -> T + Send + Sync + 'static,
+    {
+        Self::unfold(x, move |x| {
+            let y = f(&x);
+            Some((x, y))
+        })
+    }
+
+    /// Create the infinite list `x, x, x, ...`.
+    pub fn repeat(x: T) -> Self
+    where
+        T: Clone,
+    {
+        Self::unfold(x, |x| Some((x.clone(), x)))
+    }
+
+    /// Return the first element and the remaining list,
+    /// or `None` if the list is empty.
+    ///
+    /// This forces the first cell of the list.
+    pub fn uncons(&self) -> Option<(&T, &Self)> {
+        match &***self.0 {
+            Nil => None,
+            Cons(x, xs) => Some((x, xs)),
+        }
+    }
+
+    /// Return the first element of the list, if any.
+    pub fn head(&self) -> Option<&T> {
+        self.uncons().map(|(x, _)| x)
+    }
+
+    /// Return the list without its first element, if any.
+    pub fn tail(&self) -> Option<&Self> {
+        self.uncons().map(|(_, xs)| xs)
+    }
+
+    /// Return true if the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.uncons().is_none()
+    }
+
+    /// Return an iterator over references to the elements of the list.
+    ///
+    /// As long as the iterator is alive,
+    /// all cells that it has traversed are kept in memory.
+    /// To traverse long lists, consider `into_iter` instead.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter(self)
+    }
+
+    /// Lazily apply a function to all elements of the list.
+    pub fn map<U, F>(&self, f: F) -> LazyList<U>
+    where
+        U: Send + Sync + 'static,
+        F: Fn(&T) -> U + Send + Sync + 'static,
+    {
+        LazyList::unfold(self.clone(), move |l| {
+            let (x, xs) = l.uncons()?;
+            Some((f(x), xs.clone()))
+        })
+    }
+
+    /// Lazily keep only the elements of the list that satisfy a predicate.
+    pub fn filter<P>(&self, p: P) -> Self
+    where
+        T: Clone,
+        P: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        Self::unfold(self.clone(), move |mut l| loop {
+            let (x, xs) = l.uncons()?;
+            if p(x) {
+                return Some((x.clone(), xs.clone()));
+            }
+            let xs = xs.clone();
+            l = xs;
+        })
+    }
+
+    /// Lazily take the first `n` elements of the list.
+    pub fn take(&self, n: usize) -> Self
+    where
+        T: Clone,
+    {
+        Self::unfold((self.clone(), n), |(l, n)| {
+            if n == 0 {
+                return None;
+            }
+            let (x, xs) = l.uncons()?;
+            Some((x.clone(), (xs.clone(), n - 1)))
+        })
+    }
+
+    /// Lazily pair the elements of two lists,
+    /// stopping at the end of the shorter list.
+    ///
+    /// ~~~
+    /// # use lazy_mt::LazyList;
+    /// let l = LazyList::iterate(1, |n| n + 1).zip(&LazyList::repeat('a')).take(2);
+    /// assert_eq!(l.iter().cloned().collect::<Vec<_>>(), vec![(1, 'a'), (2, 'a')]);
+    /// ~~~
+    pub fn zip<U>(&self, other: &LazyList<U>) -> LazyList<(T, U)>
+    where
+        T: Clone,
+        U: Clone + Send + Sync + 'static,
+    {
+        LazyList::unfold((self.clone(), other.clone()), |(l, r)| {
+            let (x, xs) = l.uncons()?;
+            let (y, ys) = r.uncons()?;
+            Some(((x.clone(), y.clone()), (xs.clone(), ys.clone())))
+        })
+    }
+
+    /// Lazily repeat the elements of the list infinitely,
+    /// or return an empty list if the list is empty.
+    ///
+    /// ~~~
+    /// # use lazy_mt::LazyList;
+    /// let l = LazyList::cons(1, LazyList::cons(2, LazyList::nil()));
+    /// let v: Vec<_> = l.cycle().iter().take(5).copied().collect();
+    /// assert_eq!(v, vec![1, 2, 1, 2, 1]);
+    /// ~~~
+    pub fn cycle(&self) -> Self
+    where
+        T: Clone,
+    {
+        let start = self.clone();
+        Self::unfold(self.clone(), move |l| {
+            let l = if l.is_empty() { start.clone() } else { l };
+            let (x, xs) = l.uncons()?;
+            Some((x.clone(), xs.clone()))
+        })
+    }
+}
+
+// Dropping a cell drops its tail,
+// which would recurse deeply for long lists.
+// Instead, cells are dropped via `deferred::drop`.
+impl<T: 'static> Drop for LazyList<T> {
+    fn drop(&mut self) {
+        // Safe because `self.0` is not used again.
+        let cell = unsafe { ManuallyDrop::take(&mut self.0) };
+        if Arc::strong_count(&cell) > 1 {
+            // Even if this turns out to drop the cell,
+            // its tail is dropped via a new outermost drop.
+            return drop(cell);
+        }
+        deferred::drop(cell, Deferred::from_arc)
+    }
+}
+
+/// An iterator over references to the elements of a `LazyList`.
+pub struct Iter<'a, T: 'static>(&'a LazyList<T>);
+
+impl<'a, T: Send + Sync + 'static> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let (x, xs) = self.0.uncons()?;
+        self.0 = xs;
+        Some(x)
+    }
+}
+
+impl<'a, T: Send + Sync + 'static> IntoIterator for &'a LazyList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// An iterator over clones of the elements of a `LazyList`.
+///
+/// In contrast to `Iter`, this does not keep traversed cells alive.
+pub struct IntoIter<T: 'static>(LazyList<T>);
+
+impl<T: Clone + Send + Sync + 'static> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let (x, xs) = self.0.uncons()?;
+        let (x, xs) = (x.clone(), xs.clone());
+        self.0 = xs;
+        Some(x)
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> IntoIterator for LazyList<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
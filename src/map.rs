@@ -0,0 +1,193 @@
+//! Concurrent maps whose values are computed on demand.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+use crate::{ForceError, SharedLazy, SharedThunk};
+
+/// A concurrent map whose values are computed on demand.
+///
+/// The value for a key is computed at most once,
+/// even if multiple threads request it at the same time:
+/// one of them computes the value,
+/// while the others wait for the same thunk.
+/// The entries are distributed over several shards,
+/// each of which is locked separately, and
+/// no lock is held while computing a value.
+///
+/// ~~~
+/// # use lazy_mt::LazyMap;
+/// # use std::thread;
+/// let lengths = LazyMap::new(|s: &String| { println!("Computing {}", s); s.len() });
+/// thread::scope(|s| {
+///     for _ in 0..4 {
+///         // "Computing hello" is printed only once.
+///         s.spawn(|| assert_eq!(*lengths.get(&"hello".to_string()), 5));
+///     }
+/// });
+/// ~~~
+pub struct LazyMap<K, V> {
+    f: Arc<dyn Fn(&K) -> V + Send + Sync>,
+    hasher: RandomState,
+    shards: Box<[Shard<K, V>]>,
+}
+
+type Shard<K, V> = RwLock<HashMap<K, SharedLazy<V>>>;
+
+impl<K, V> LazyMap<K, V>
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Send + Sync + 'static,
+{
+    /// Create an empty map that computes the value for a key with `f`.
+    pub fn new(f: impl Fn(&K) -> V + Send + Sync + 'static) -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let shards = (threads * 4).next_power_of_two();
+        LazyMap {
+            f: Arc::new(f),
+            hasher: RandomState::new(),
+            shards: (0..shards).map(|_| RwLock::default()).collect(),
+        }
+    }
+
+    /// Return the value for a key, computing it if necessary.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `Thunk::force` if
+    /// the computation panicked or the value depends on itself.
+    pub fn get(&self, k: &K) -> SharedLazy<V> {
+        self.try_get(k).unwrap_or_else(|e| e.raise())
+    }
+
+    /// Return the value for a key, computing it if necessary,
+    /// or the reason why it could not be computed.
+    pub fn try_get(&self, k: &K) -> Result<SharedLazy<V>, ForceError> {
+        let thunk = self.entry(k, || {
+            let (f, k) = (self.f.clone(), k.clone());
+            Box::new(move || f(&k))
+        });
+        thunk.thunk().try_force()?;
+        Ok(thunk)
+    }
+
+    /// Return the value for a key,
+    /// computing it with `f` instead of the function of the map if necessary.
+    ///
+    /// ~~~
+    /// # use lazy_mt::LazyMap;
+    /// let map = LazyMap::new(|n: &u32| n * 2);
+    /// assert_eq!(*map.get_or_compute(1, || 5), 5);
+    /// assert_eq!(*map.get(&1), 5);
+    /// ~~~
+    pub fn get_or_compute(&self, k: K, f: impl FnOnce() -> V + Send + 'static) -> SharedLazy<V> {
+        let thunk = self.entry(&k, || Box::new(f));
+        thunk.thunk().force();
+        thunk
+    }
+
+    /// Return the thunk for a key, inserting a new one if there is none.
+    fn entry(&self, k: &K, f: impl FnOnce() -> Box<dyn FnOnce() -> V + Send>) -> SharedLazy<V> {
+        let shard = self.shard(k);
+        if let Some(thunk) = shard.read().unwrap().get(k) {
+            return thunk.clone();
+        }
+        let mut shard = shard.write().unwrap();
+        let thunk = shard
+            .entry(k.clone())
+            .or_insert_with(|| SharedThunk::new(f()));
+        thunk.clone()
+    }
+}
+
+impl<K: Eq + Hash, V> LazyMap<K, V> {
+    fn shard(&self, k: &K) -> &Shard<K, V> {
+        let hash = self.hasher.hash_one(k) as usize;
+        &self.shards[hash & (self.shards.len() - 1)]
+    }
+
+    /// Remove a key from the map, returning its thunk if there was one.
+    ///
+    /// Threads that are already waiting for the value of the key
+    /// still obtain the value of the removed thunk,
+    /// whereas later requests compute a new value.
+    ///
+    /// ~~~
+    /// # use lazy_mt::LazyMap;
+    /// # use std::sync::atomic::{AtomicUsize, Ordering};
+    /// static CALLS: AtomicUsize = AtomicUsize::new(0);
+    /// let map = LazyMap::new(|_: &u32| CALLS.fetch_add(1, Ordering::Relaxed));
+    /// assert_eq!(*map.get(&1), 0);
+    /// assert!(map.remove(&1).is_some());
+    /// assert_eq!(*map.get(&1), 1);
+    /// ~~~
+    pub fn remove(&self, k: &K) -> Option<SharedLazy<V>> {
+        self.shard(k).write().unwrap().remove(k)
+    }
+
+    /// Return true if the map contains a thunk for the key,
+    /// regardless of whether it has been evaluated.
+    pub fn contains_key(&self, k: &K) -> bool {
+        self.shard(k).read().unwrap().contains_key(k)
+    }
+}
+
+impl<K, V> LazyMap<K, V> {
+    /// Return the number of entries in the map,
+    /// including those that have not been evaluated yet.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.read().unwrap().len()).sum()
+    }
+
+    /// Return true if the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|s| s.read().unwrap().is_empty())
+    }
+
+    /// Return an iterator over all evaluated entries of the map.
+    ///
+    /// Every shard is locked only while copying its evaluated entries,
+    /// so entries that are inserted or removed during the iteration
+    /// may or may not be yielded.
+    ///
+    /// ~~~
+    /// # use lazy_mt::LazyMap;
+    /// let map = LazyMap::new(|n: &u32| n * 2);
+    /// map.get(&1);
+    /// map.get(&2);
+    /// let mut entries: Vec<_> = map.iter().map(|(k, v)| (k, *v)).collect();
+    /// entries.sort();
+    /// assert_eq!(entries, vec![(1, 2), (2, 4)]);
+    /// ~~~
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            shards: self.shards.iter(),
+            entries: Vec::new().into_iter(),
+        }
+    }
+}
+
+/// An iterator over the evaluated entries of a `LazyMap`.
+pub struct Iter<'a, K, V> {
+    shards: std::slice::Iter<'a, Shard<K, V>>,
+    entries: std::vec::IntoIter<(K, SharedLazy<V>)>,
+}
+
+impl<K: Clone, V> Iterator for Iter<'_, K, V> {
+    type Item = (K, SharedLazy<V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(entry);
+            }
+            let shard = self.shards.next()?.read().unwrap();
+            let evaluated = shard.iter().filter(|(_, v)| v.thunk().is_evaluated());
+            let entries: Vec<_> = evaluated.map(|(k, v)| (k.clone(), v.clone())).collect();
+            self.entries = entries.into_iter();
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Memoized functions.
+
+use std::hash::Hash;
+use std::sync::{Arc, Weak};
+
+use crate::{ForceError, LazyMap, SharedLazy};
+
+/// A memoized function, as created by `memoize`.
+///
+/// Cloning a memoized function yields another handle to
+/// the same function and its memoized results.
+pub struct Memo<K, V>(Arc<LazyMap<K, V>>);
+
+impl<K, V> Clone for Memo<K, V> {
+    fn clone(&self) -> Self {
+        Memo(self.0.clone())
+    }
+}
+
+/// Memoize a function that may recursively call itself.
+///
+/// The function receives the memoized function itself as first argument,
+/// which it can use to recursively obtain results for other keys.
+/// The result for every key is computed at most once,
+/// even if multiple threads request it at the same time.
+///
+/// ~~~
+/// # use lazy_mt::memoize;
+/// let fib = memoize(|fib, n: u64| if n < 2 { n } else { *fib.get(n - 1) + *fib.get(n - 2) });
+/// assert_eq!(*fib.get(90), 2880067194370816120);
+/// ~~~
+///
+/// If the result for a key depends on itself,
+/// this is reported as a `CycleError` instead of deadlocking:
+///
+/// ~~~
+/// # use lazy_mt::{memoize, CycleError};
+/// # use std::panic::{self, AssertUnwindSafe};
+/// let f = memoize(|f, n: u32| -> u32 { if n == 0 { *f.get(2) } else { *f.get(n - 1) } });
+/// let err = panic::catch_unwind(AssertUnwindSafe(|| *f.get(2))).unwrap_err();
+/// assert_eq!(err.downcast_ref::<CycleError>().unwrap().thunks().len(), 3);
+/// ~~~
+pub fn memoize<K, V, F>(f: F) -> Memo<K, V>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+    F: Fn(&Memo<K, V>, K) -> V + Send + Sync + 'static,
+{
+    Memo(Arc::new_cyclic(|map: &Weak<LazyMap<K, V>>| {
+        let map = map.clone();
+        LazyMap::new(move |k: &K| {
+            // The map is alive while one of its values is computed.
+            let memo = Memo(map.upgrade().unwrap());
+            f(&memo, k.clone())
+        })
+    }))
+}
+
+impl<K, V> Memo<K, V>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    /// Return the result for a key, computing it if necessary.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `Thunk::force` if
+    /// the computation panicked or the result depends on itself.
+    pub fn get(&self, k: K) -> SharedLazy<V> {
+        self.0.get(&k)
+    }
+
+    /// Return the result for a key, computing it if necessary,
+    /// or the reason why it could not be computed.
+    pub fn try_get(&self, k: K) -> Result<SharedLazy<V>, ForceError> {
+        self.0.try_get(&k)
+    }
+
+    /// Return the map of memoized results,
+    /// for example to iterate over the results computed so far.
+    pub fn map(&self) -> &LazyMap<K, V> {
+        &self.0
+    }
+}
@@ -0,0 +1,156 @@
+//! Parallel forcing of many thunks.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::{Evaluate, Failure, Thunk, TryEvaluate, TryThunk};
+
+/// Force all given thunks in parallel.
+///
+/// The thunks are distributed over as many threads as
+/// the system supports running in parallel.
+///
+/// # Panics
+///
+/// If forcing any thunk panics,
+/// this resumes the panic after all other thunks have been forced.
+///
+/// ~~~
+/// # use lazy_mt::{force_all, Thunk};
+/// let thunks: Vec<_> = (0..100u64).map(|i| Thunk::new(move || i * i)).collect();
+/// force_all(&thunks);
+/// assert!(thunks.iter().all(|t| t.is_evaluated()));
+/// ~~~
+///
+/// ~~~
+/// # use lazy_mt::{force_all, lazy, ThunkState};
+/// # use std::panic;
+/// let thunks: Vec<_> = (0..8).map(|i| lazy!(if i == 0 { panic!("oops") } else { i })).collect();
+/// assert!(panic::catch_unwind(|| force_all(&thunks)).is_err());
+/// assert_eq!(thunks[0].state(), ThunkState::Poisoned);
+/// assert!(thunks[1..].iter().all(|t| t.is_evaluated()));
+/// ~~~
+pub fn force_all<'a, E, V, I>(thunks: I)
+where
+    I: IntoIterator<Item = &'a Thunk<E, V>>,
+    E: Evaluate<V> + Send + 'a,
+    V: Send + Sync + 'a,
+{
+    let thunks: Vec<_> = thunks.into_iter().collect();
+    parallel(thunks.len(), |i| thunks[i].force());
+}
+
+/// Force all given fallible thunks in parallel,
+/// returning either all values or all errors.
+///
+/// Values and errors are returned in the order of the thunks,
+/// where every error is accompanied by the index of its thunk.
+///
+/// ~~~
+/// # use lazy_mt::{try_force_all, TryThunk};
+/// let thunks: Vec<_> = ["1", "x", "3", "y"]
+///     .iter()
+///     .map(|s| TryThunk::new(move || s.parse::<u32>()))
+///     .collect();
+/// let errors = try_force_all(&thunks).unwrap_err();
+/// let indices: Vec<_> = errors.iter().map(|(i, _)| *i).collect();
+/// assert_eq!(indices, vec![1, 3]);
+/// ~~~
+pub fn try_force_all<'a, E, V, Err, I>(
+    thunks: I,
+) -> Result<Vec<&'a V>, Vec<(usize, Failure<'a, Err>)>>
+where
+    I: IntoIterator<Item = &'a TryThunk<E, V, Err>>,
+    E: TryEvaluate<V, Err> + Send + 'a,
+    V: Send + Sync + 'a,
+    Err: Send + Sync + 'a,
+{
+    let thunks: Vec<_> = thunks.into_iter().collect();
+    aggregate(parallel(thunks.len(), |i| thunks[i].force()))
+}
+
+/// Force all given thunks in parallel using `rayon`.
+#[cfg(feature = "rayon")]
+pub fn par_force<E, V>(thunks: &[Thunk<E, V>])
+where
+    E: Evaluate<V> + Send,
+    V: Send + Sync,
+{
+    use rayon::prelude::*;
+    thunks.par_iter().for_each(Thunk::force)
+}
+
+/// Force all given fallible thunks in parallel using `rayon`,
+/// returning either all values or all errors like `try_force_all`.
+#[cfg(feature = "rayon")]
+pub fn par_try_force<E, V, Err>(
+    thunks: &[TryThunk<E, V, Err>],
+) -> Result<Vec<&V>, Vec<(usize, Failure<'_, Err>)>>
+where
+    E: TryEvaluate<V, Err> + Send,
+    V: Send + Sync,
+    Err: Send + Sync,
+{
+    use rayon::prelude::*;
+    aggregate(thunks.par_iter().map(TryThunk::force).collect())
+}
+
+/// Apply a function to all indices below `len` in parallel,
+/// returning the results in order.
+///
+/// If the function panics for any index,
+/// the first panic is resumed after all other indices have been processed.
+pub(crate) fn parallel<R: Send>(len: usize, f: impl Fn(usize) -> R + Sync) -> Vec<R> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let next = AtomicUsize::new(0);
+    let first_panic = Mutex::new(None);
+    let worker = || {
+        let mut results = Vec::new();
+        loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            if i >= len {
+                return results;
+            }
+            match panic::catch_unwind(AssertUnwindSafe(|| f(i))) {
+                Ok(r) => results.push((i, r)),
+                // Keep the worker alive, so that it processes the remaining indices.
+                Err(p) => {
+                    first_panic.lock().unwrap().get_or_insert(p);
+                }
+            }
+        }
+    };
+
+    let mut results: Vec<_> = thread::scope(|s| {
+        let handles: Vec<_> = (0..threads.min(len)).map(|_| s.spawn(worker)).collect();
+        // Workers do not panic, because they catch all panics of `f`.
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        results.into_iter().flatten().collect()
+    });
+    if let Some(p) = first_panic.into_inner().unwrap() {
+        panic::resume_unwind(p)
+    }
+    results.sort_unstable_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Return all values if there are no errors, otherwise all errors.
+fn aggregate<'a, V, Err>(
+    results: Vec<Result<&'a V, Failure<'a, Err>>>,
+) -> Result<Vec<&'a V>, Vec<(usize, Failure<'a, Err>)>> {
+    let mut values = Vec::with_capacity(results.len());
+    let mut errors = Vec::new();
+    for (i, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(val) => values.push(val),
+            Err(err) => errors.push((i, err)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(values)
+    } else {
+        Err(errors)
+    }
+}
@@ -0,0 +1,190 @@
+//! Lazily evaluated values that can be reset.
+
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::{Evaluate, Thunk, ThunkState};
+
+/// A lazily evaluated value that can be reset,
+/// such that it is evaluated again on the next access.
+///
+/// Every reset starts a new generation of the thunk.
+/// Forcing the thunk yields a snapshot of its current generation,
+/// which remains valid after the thunk is reset,
+/// but whose generation number then reveals that it is stale.
+///
+/// ~~~
+/// # use lazy_mt::ResettableThunk;
+/// let load = |debug: bool| move || format!("debug={}", debug);
+/// let config = ResettableThunk::new(load(false));
+/// let old = config.force();
+/// assert_eq!(*old, "debug=false");
+///
+/// config.reset(load(true));
+/// assert!(config.is_stale(&old));
+/// assert_eq!(*old, "debug=false");
+/// assert_eq!(*config.force(), "debug=true");
+/// ~~~
+///
+/// Evaluators do not need to be `Clone` or `Sync`,
+/// so boxed closures can be supplied on every reset:
+///
+/// ~~~
+/// # use lazy_mt::ResettableThunk;
+/// # use std::{sync::Arc, thread};
+/// type Load = Box<dyn FnOnce() -> String + Send>;
+/// let config: Arc<ResettableThunk<Load, String>> =
+///     Arc::new(ResettableThunk::new(Box::new(|| "v1".to_string())));
+/// let c = config.clone();
+/// thread::spawn(move || assert_eq!(*c.force(), "v1")).join().unwrap();
+///
+/// config.reset(Box::new(|| "v2".to_string()));
+/// assert_eq!(*config.force(), "v2");
+/// ~~~
+///
+/// In contrast to `Thunk`, every access takes a read lock
+/// on the current generation and clones a reference to it,
+/// even if the generation has already been evaluated.
+pub struct ResettableThunk<E, V> {
+    current: RwLock<Arc<Generation<E, V>>>,
+    /// The evaluator that `invalidate` resets the thunk to, if any.
+    ///
+    /// This is not behind `current`, so that
+    /// the thunk is `Sync` even if the evaluator is not.
+    template: Mutex<Option<E>>,
+}
+
+struct Generation<E, V> {
+    number: u64,
+    thunk: Thunk<E, V>,
+}
+
+/// A generation of a `ResettableThunk`.
+///
+/// This dereferences to the value of the generation.
+pub struct Snapshot<E, V>(Arc<Generation<E, V>>);
+
+impl<E, V> Clone for Snapshot<E, V> {
+    fn clone(&self) -> Self {
+        Snapshot(self.0.clone())
+    }
+}
+
+impl<E, V> Snapshot<E, V> {
+    /// Return the generation number of the snapshot.
+    pub fn generation(&self) -> u64 {
+        self.0.number
+    }
+}
+
+impl<E, V> Deref for Snapshot<E, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        // Snapshots are only created from evaluated thunks.
+        self.0.thunk.get().unwrap()
+    }
+}
+
+impl<E: Evaluate<V>, V> ResettableThunk<E, V> {
+    /// Create a lazily evaluated value that can be reset.
+    pub fn new(e: E) -> Self {
+        Self::from_parts(e, None)
+    }
+
+    fn from_parts(e: E, template: Option<E>) -> Self {
+        ResettableThunk {
+            current: RwLock::new(Arc::new(Generation::new(0, e))),
+            template: Mutex::new(template),
+        }
+    }
+
+    /// Force evaluation of the current generation,
+    /// returning a snapshot of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `Thunk::force`.
+    /// If the evaluation panicked, the thunk remains poisoned until it is reset.
+    pub fn force(&self) -> Snapshot<E, V> {
+        let current = self.current.read().unwrap().clone();
+        current.thunk.force();
+        Snapshot(current)
+    }
+
+    /// Reset the thunk to a new evaluator, returning the new generation number.
+    ///
+    /// Threads that are evaluating the previous generation
+    /// finish its evaluation, but the result belongs to
+    /// the previous generation only.
+    /// The template of the thunk remains unchanged.
+    pub fn reset(&self, e: E) -> u64 {
+        let mut current = self.current.write().unwrap();
+        let number = current.number + 1;
+        *current = Arc::new(Generation::new(number, e));
+        number
+    }
+}
+
+impl<E: Evaluate<V> + Clone, V> ResettableThunk<E, V> {
+    /// Create a lazily evaluated value that can be reset,
+    /// keeping the evaluator as template for `invalidate`.
+    pub fn with_template(e: E) -> Self {
+        Self::from_parts(e.clone(), Some(e))
+    }
+
+    /// Reset the thunk to a clone of its template,
+    /// returning the new generation number.
+    ///
+    /// If the thunk was not created by `with_template`,
+    /// it has no template, so this does nothing and returns `None`.
+    ///
+    /// ~~~
+    /// # use lazy_mt::ResettableThunk;
+    /// # use std::sync::atomic::{AtomicUsize, Ordering};
+    /// static LOADS: AtomicUsize = AtomicUsize::new(0);
+    /// let x = ResettableThunk::with_template(|| LOADS.fetch_add(1, Ordering::Relaxed));
+    /// assert_eq!(*x.force(), 0);
+    /// assert_eq!(x.invalidate(), Some(1));
+    /// assert_eq!(*x.force(), 1);
+    /// ~~~
+    pub fn invalidate(&self) -> Option<u64> {
+        let e = self.template.lock().unwrap().clone()?;
+        Some(self.reset(e))
+    }
+}
+
+impl<E, V> ResettableThunk<E, V> {
+    /// Return the current generation number.
+    pub fn generation(&self) -> u64 {
+        self.current.read().unwrap().number
+    }
+
+    /// Return true if the snapshot belongs to a previous generation.
+    pub fn is_stale(&self, snapshot: &Snapshot<E, V>) -> bool {
+        snapshot.generation() != self.generation()
+    }
+
+    /// Return the evaluation state of the current generation,
+    /// without forcing it.
+    pub fn state(&self) -> ThunkState {
+        self.current.read().unwrap().thunk.state()
+    }
+
+    /// Return a snapshot of the current generation if it has been evaluated,
+    /// without forcing it.
+    pub fn get(&self) -> Option<Snapshot<E, V>> {
+        let current = self.current.read().unwrap().clone();
+        current.thunk.get()?;
+        Some(Snapshot(current))
+    }
+}
+
+impl<E: Evaluate<V>, V> Generation<E, V> {
+    fn new(number: u64, e: E) -> Self {
+        Generation {
+            number,
+            thunk: Thunk::new(e),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Serialization of thunks, enabled by the `serde` feature.
+//!
+//! Serializing a thunk forces it and serializes its value.
+//! Deserializing a thunk yields an evaluated thunk.
+//!
+//! ~~~
+//! # use lazy_mt::{lazy, Lazy};
+//! let x = lazy!(vec![1, 2, 3]);
+//! assert_eq!(serde_json::to_string(&x).unwrap(), "[1,2,3]");
+//!
+//! let y: Lazy<Vec<u32>> = serde_json::from_str("[1,2,3]").unwrap();
+//! assert!(y.is_evaluated());
+//! assert_eq!(*y, vec![1, 2, 3]);
+//! ~~~
+//!
+//! To serialize unevaluated thunks as absent instead of forcing them,
+//! use `skip_unevaluated` together with `#[serde(skip_serializing_if)]`.
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Error, Serialize, Serializer};
+
+use crate::{Evaluate, Thunk};
+
+impl<E, V> Serialize for Thunk<E, V>
+where
+    E: Evaluate<V>,
+    V: Serialize,
+{
+    /// Force evaluation of the thunk and serialize its value.
+    ///
+    /// If the thunk cannot be forced, this fails with the `ForceError`.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.try_force()
+            .map_err(S::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+impl<'de, E, V> Deserialize<'de> for Thunk<E, V>
+where
+    E: Evaluate<V>,
+    V: Deserialize<'de>,
+{
+    /// Deserialize a value into an evaluated thunk.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        V::deserialize(deserializer).map(Thunk::evaluated)
+    }
+}
+
+/// Return true if the thunk has not been evaluated yet.
+///
+/// This is meant to be used with `#[serde(skip_serializing_if)]`,
+/// such that unevaluated thunks are omitted instead of being forced.
+/// Absent fields then need a `#[serde(default)]` to be deserialized.
+///
+/// ~~~
+/// # use lazy_mt::{lazy, Lazy};
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Serialize, Deserialize)]
+/// struct Report {
+///     title: String,
+///     #[serde(skip_serializing_if = "lazy_mt::serde::skip_unevaluated")]
+///     #[serde(default = "no_summary")]
+///     summary: Lazy<String>,
+/// }
+///
+/// fn no_summary() -> Lazy<String> {
+///     lazy!(String::new())
+/// }
+///
+/// let report = Report {
+///     title: "Q3".to_string(),
+///     summary: lazy!("All good".to_string()),
+/// };
+/// let json = serde_json::to_string(&report).unwrap();
+/// assert_eq!(json, r#"{"title":"Q3"}"#);
+///
+/// report.summary.force();
+/// let json = serde_json::to_string(&report).unwrap();
+/// assert_eq!(json, r#"{"title":"Q3","summary":"All good"}"#);
+///
+/// let report: Report = serde_json::from_str(r#"{"title":"Q4"}"#).unwrap();
+/// assert_eq!(*report.summary, "");
+/// ~~~
+pub fn skip_unevaluated<E, V>(thunk: &Thunk<E, V>) -> bool {
+    !thunk.is_evaluated()
+}
@@ -0,0 +1,114 @@
+//! Lazily evaluated values with shared ownership.
+
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::{Evaluate, Thunk};
+
+/// A lazily evaluated value with shared ownership.
+///
+/// Cloning a shared thunk yields another handle to the same thunk,
+/// which is evaluated at most once.
+///
+/// ~~~
+/// # use lazy_mt::SharedThunk;
+/// # use std::thread;
+/// let x = SharedThunk::new(|| { println!("Evaluated!"); 7 });
+/// let y = x.clone();
+///
+/// // "Evaluated!" is printed below this line.
+/// thread::spawn(move || assert_eq!(*y, 7));
+/// assert_eq!(*x, 7);
+/// ~~~
+pub struct SharedThunk<E, V>(Arc<Thunk<E, V>>);
+
+/// A lazily evaluated value with shared ownership produced from a closure.
+pub type SharedLazy<T> = SharedThunk<Box<dyn FnOnce() -> T + Send>, T>;
+
+impl<E, V> Clone for SharedThunk<E, V> {
+    fn clone(&self) -> Self {
+        SharedThunk(self.0.clone())
+    }
+}
+
+impl<E, V: fmt::Debug> fmt::Debug for SharedThunk<E, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<E, V> From<Thunk<E, V>> for SharedThunk<E, V> {
+    fn from(thunk: Thunk<E, V>) -> Self {
+        SharedThunk(Arc::new(thunk))
+    }
+}
+
+impl<E, V> SharedThunk<E, V>
+where
+    E: Evaluate<V>,
+{
+    /// Create a shared lazily evaluated value from
+    /// a value implementing the `Evaluate` trait.
+    pub fn new(e: E) -> Self {
+        Thunk::new(e).into()
+    }
+
+    /// Create a new, evaluated, shared thunk from a value.
+    pub fn evaluated(val: V) -> Self {
+        Thunk::evaluated(val).into()
+    }
+
+    /// Return the value of the thunk if there are no other handles to it,
+    /// forcing its evaluation if necessary.
+    ///
+    /// Otherwise, the shared thunk is returned.
+    ///
+    /// ~~~
+    /// # use lazy_mt::SharedThunk;
+    /// let x = SharedThunk::new(|| vec![1, 2, 3]);
+    /// let y = x.clone();
+    /// let x = SharedThunk::try_unwrap(x).unwrap_err();
+    /// drop(y);
+    /// assert_eq!(SharedThunk::try_unwrap(x).ok(), Some(vec![1, 2, 3]));
+    /// ~~~
+    pub fn try_unwrap(this: Self) -> Result<V, Self> {
+        Thunk::try_unwrap(this.0).map_err(SharedThunk)
+    }
+}
+
+impl<E, V> SharedThunk<E, V> {
+    /// Return the underlying thunk,
+    /// for example to inspect its state without forcing it.
+    pub fn thunk(&self) -> &Thunk<E, V> {
+        &self.0
+    }
+
+    /// Return true if both handles refer to the same thunk.
+    ///
+    /// ~~~
+    /// # use lazy_mt::SharedThunk;
+    /// let x = SharedThunk::new(|| 1);
+    /// let y = x.clone();
+    /// assert!(SharedThunk::ptr_eq(&x, &y));
+    /// ~~~
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Arc::ptr_eq(&this.0, &other.0)
+    }
+
+    /// Return the number of handles to the thunk.
+    pub fn strong_count(this: &Self) -> usize {
+        Arc::strong_count(&this.0)
+    }
+}
+
+impl<E, V: Send + Sync> Deref for SharedThunk<E, V>
+where
+    E: Evaluate<V>,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.0
+    }
+}
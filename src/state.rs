@@ -0,0 +1,329 @@
+//! The evaluation state machine shared by all kinds of thunks.
+
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Instant;
+
+use crate::cycle::{self, ThunkId};
+use crate::{ForceError, Panic};
+
+// The lowest bits of the state word are one of the following tags.
+// While evaluating, the remaining bits identify the evaluating thread.
+pub(crate) const UNEVALUATED: usize = 0;
+pub(crate) const EVALUATING: usize = 1;
+pub(crate) const EVALUATED: usize = 2;
+pub(crate) const POISONED: usize = 3;
+pub(crate) const TAG: usize = 0b11;
+pub(crate) const OWNER_SHIFT: u32 = 2;
+
+/// The minimal remaining stack size below which
+/// a newly allocated stack segment is used.
+const RED_ZONE: usize = 128 * 1024;
+/// The size of newly allocated stack segments.
+const STACK_SIZE: usize = 2 * 1024 * 1024;
+
+/// Run a function that may recurse deeply through long chains of thunks,
+/// growing the stack if needed.
+pub(crate) fn grow_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, STACK_SIZE, f)
+}
+
+/// The evaluation state of a thunk, as observed at some point in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ThunkState {
+    /// The thunk has not been evaluated yet.
+    Unevaluated,
+    /// A thread is currently evaluating the thunk.
+    Evaluating,
+    /// The thunk has been evaluated.
+    Evaluated,
+    /// The evaluation of the thunk panicked.
+    Poisoned,
+}
+
+/// The evaluation state of a thunk.
+///
+/// A thunk stores its evaluator or value next to its state,
+/// and may only write to it after having claimed its evaluation,
+/// and only read from it once the state is `EVALUATED`.
+pub(crate) struct State {
+    word: Word,
+    parking: Parking<Option<Panic>>,
+}
+
+/// The state word of a thunk.
+///
+/// Threads wait for the evaluation of the thunk in a `Parking`,
+/// which may be shared by the state words of many thunks,
+/// each of which is identified in it by a key.
+pub(crate) struct Word(AtomicUsize);
+
+/// A place where threads wait for evaluations to finish,
+/// holding the panics that poisoned thunks.
+pub(crate) struct Parking<P> {
+    panics: Mutex<P>,
+    done: Condvar,
+}
+
+/// The panics that poisoned thunks, by key.
+pub(crate) trait Panics {
+    fn get(&self, key: usize) -> Option<&Panic>;
+    fn set(&mut self, key: usize, panic: Option<Panic>);
+}
+
+/// The panic of a single thunk, which ignores keys.
+impl Panics for Option<Panic> {
+    fn get(&self, _: usize) -> Option<&Panic> {
+        self.as_ref()
+    }
+
+    fn set(&mut self, _: usize, panic: Option<Panic>) {
+        *self = panic
+    }
+}
+
+impl Panics for HashMap<usize, Panic> {
+    fn get(&self, key: usize) -> Option<&Panic> {
+        HashMap::get(self, &key)
+    }
+
+    fn set(&mut self, key: usize, panic: Option<Panic>) {
+        match panic {
+            Some(panic) => self.insert(key, panic),
+            None => self.remove(&key),
+        };
+    }
+}
+
+/// The outcome of trying to claim the evaluation of a thunk.
+pub(crate) enum Claim {
+    /// The current thread has to evaluate the thunk.
+    Evaluate,
+    /// The thunk has been evaluated.
+    Evaluated,
+}
+
+impl State {
+    pub fn new(tag: usize) -> Self {
+        State {
+            word: Word::new(tag),
+            parking: Parking::new(None),
+        }
+    }
+
+    #[inline]
+    pub fn is_evaluated(&self) -> bool {
+        self.word.is_evaluated()
+    }
+
+    pub fn get(&self) -> ThunkState {
+        self.word.get()
+    }
+
+    /// See `Word::claim`.
+    pub fn claim(&self, id: ThunkId, deadline: Option<Instant>) -> Result<Claim, ForceError> {
+        self.word.claim(&self.parking, 0, id, deadline)
+    }
+
+    /// See `Word::evaluate`.
+    pub fn evaluate<R>(&self, id: ThunkId, f: impl FnOnce() -> R) -> R {
+        self.word.evaluate(&self.parking, 0, id, f)
+    }
+
+    /// See `Word::finish`.
+    pub fn finish(&self, tag: usize) {
+        self.word.finish(&self.parking, 0, tag)
+    }
+
+    /// See `Word::unpoison`.
+    pub fn unpoison(&self, reset: impl FnOnce()) -> bool {
+        self.word.unpoison(&self.parking, 0, reset)
+    }
+
+    /// Return the panic that poisoned the thunk, if any.
+    pub fn panic(&self) -> Option<Panic> {
+        self.word.panic(&self.parking, 0)
+    }
+
+    /// See `Word::wait`.
+    pub fn wait(&self, id: ThunkId, deadline: Option<Instant>) -> Result<(), ForceError> {
+        self.word.wait(&self.parking, id, deadline)
+    }
+}
+
+impl<P> Parking<P> {
+    pub fn new(panics: P) -> Self {
+        Parking {
+            panics: Mutex::new(panics),
+            done: Condvar::new(),
+        }
+    }
+}
+
+impl Word {
+    pub fn new(tag: usize) -> Self {
+        Word(AtomicUsize::new(tag))
+    }
+
+    #[inline]
+    pub fn is_evaluated(&self) -> bool {
+        self.0.load(Ordering::Acquire) == EVALUATED
+    }
+
+    pub fn get(&self) -> ThunkState {
+        match self.0.load(Ordering::Acquire) & TAG {
+            UNEVALUATED => ThunkState::Unevaluated,
+            EVALUATING => ThunkState::Evaluating,
+            EVALUATED => ThunkState::Evaluated,
+            _ => ThunkState::Poisoned,
+        }
+    }
+
+    /// Claim the evaluation of a thunk,
+    /// waiting if another thread is currently evaluating it,
+    /// but no longer than until the deadline (if given).
+    ///
+    /// If this returns `Claim::Evaluate`, then
+    /// the caller must eventually call `finish`,
+    /// unless its evaluation panics inside `evaluate`.
+    #[cold]
+    pub fn claim<P: Panics>(
+        &self,
+        parking: &Parking<P>,
+        key: usize,
+        id: ThunkId,
+        deadline: Option<Instant>,
+    ) -> Result<Claim, ForceError> {
+        let me = cycle::thread();
+        loop {
+            let cas = self.0.compare_exchange(
+                UNEVALUATED,
+                EVALUATING | me << OWNER_SHIFT,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            );
+            let word = match cas {
+                // We are the thread responsible for doing the evaluation.
+                Ok(_) => return Ok(Claim::Evaluate),
+                Err(word) => word,
+            };
+            match word & TAG {
+                EVALUATING if word >> OWNER_SHIFT == me => {
+                    return Err(ForceError::Cycle(cycle::reentrant(id)))
+                }
+                EVALUATING => self.wait(parking, id, deadline)?,
+                EVALUATED => return Ok(Claim::Evaluated),
+                POISONED => {
+                    // If the thunk was retried in the meantime, try again.
+                    if let Some(p) = self.panic(parking, key) {
+                        return Err(ForceError::Poisoned(p));
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// Run the evaluation of a claimed thunk.
+    ///
+    /// If the evaluation panics, this poisons the thunk
+    /// and resumes the panic.
+    pub fn evaluate<P: Panics, R>(
+        &self,
+        parking: &Parking<P>,
+        key: usize,
+        id: ThunkId,
+        f: impl FnOnce() -> R,
+    ) -> R {
+        let _evaluation = cycle::Evaluation::new(id);
+        // Evaluations that force other thunks nest on the stack.
+        let f = || grow_stack(f);
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(r) => r,
+            Err(payload) => {
+                let panic = Some(Panic::new(&*payload));
+                self.publish(parking, key, POISONED, panic);
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+
+    /// Finish the evaluation of a claimed thunk,
+    /// setting its state to either `EVALUATED` or `UNEVALUATED`.
+    pub fn finish<P: Panics>(&self, parking: &Parking<P>, key: usize, tag: usize) {
+        self.publish(parking, key, tag, None)
+    }
+
+    /// Reset a poisoned thunk to `UNEVALUATED`,
+    /// running `reset` before any other thread can observe the new state.
+    ///
+    /// Return whether the thunk was poisoned.
+    pub fn unpoison<P: Panics>(
+        &self,
+        parking: &Parking<P>,
+        key: usize,
+        reset: impl FnOnce(),
+    ) -> bool {
+        let mut panics = parking.panics.lock().unwrap();
+        if self.0.load(Ordering::Acquire) != POISONED {
+            return false;
+        }
+        reset();
+        panics.set(key, None);
+        self.0.store(UNEVALUATED, Ordering::Release);
+        true
+    }
+
+    /// Return the panic that poisoned the thunk, if any.
+    pub fn panic<P: Panics>(&self, parking: &Parking<P>, key: usize) -> Option<Panic> {
+        parking.panics.lock().unwrap().get(key).cloned()
+    }
+
+    /// Publish the final state of an evaluation,
+    /// waking up all threads waiting for it.
+    fn publish<P: Panics>(
+        &self,
+        parking: &Parking<P>,
+        key: usize,
+        tag: usize,
+        panic: Option<Panic>,
+    ) {
+        // Taking the lock ensures that no waiter misses the notification
+        // between checking the state and going to sleep.
+        let mut panics = parking.panics.lock().unwrap();
+        panics.set(key, panic);
+        self.0.store(tag, Ordering::Release);
+        drop(panics);
+        parking.done.notify_all();
+    }
+
+    /// Wait until no thread is evaluating the thunk,
+    /// but no longer than until the deadline (if given).
+    ///
+    /// If the parking is shared, this may wake up spuriously
+    /// when other thunks finish their evaluation.
+    pub fn wait<P>(
+        &self,
+        parking: &Parking<P>,
+        id: ThunkId,
+        deadline: Option<Instant>,
+    ) -> Result<(), ForceError> {
+        let _waiting = cycle::Waiting::new(id, &self.0).map_err(ForceError::Cycle)?;
+        let mut lock = parking.panics.lock().unwrap();
+        while self.0.load(Ordering::Acquire) & TAG == EVALUATING {
+            lock = match deadline {
+                None => parking.done.wait(lock).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(ForceError::TimedOut);
+                    }
+                    parking.done.wait_timeout(lock, deadline - now).unwrap().0
+                }
+            };
+        }
+        Ok(())
+    }
+}
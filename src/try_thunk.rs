@@ -0,0 +1,223 @@
+//! Lazily evaluated values whose evaluation may fail.
+
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::mem;
+use std::ops::Deref;
+use std::panic::RefUnwindSafe;
+
+use self::Inner::{Error, Evaluating, Unevaluated, Value};
+use crate::state::{Claim, State, EVALUATED, UNEVALUATED};
+use crate::{ThunkId, ThunkState};
+
+/// Fallible evaluation of a value.
+///
+/// In contrast to `Evaluate`, the evaluator is only borrowed,
+/// so that evaluation can be attempted again after a failure.
+pub trait TryEvaluate<V, Err> {
+    /// Attempt to evaluate a value.
+    fn try_evaluate(&mut self) -> Result<V, Err>;
+}
+
+impl<V, Err, F: FnMut() -> Result<V, Err>> TryEvaluate<V, Err> for F {
+    fn try_evaluate(&mut self) -> Result<V, Err> {
+        self()
+    }
+}
+
+/// What happens to a `TryThunk` after its evaluation failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// The error is cached and returned by every further access.
+    Cache,
+    /// The thunk becomes unevaluated again,
+    /// so that the next access retries its evaluation.
+    Retry,
+}
+
+/// An error obtained by forcing a `TryThunk`.
+///
+/// This dereferences to the error.
+pub enum Failure<'a, Err> {
+    /// The error was cached by the thunk.
+    Cached(&'a Err),
+    /// The error was just produced by an evaluation,
+    /// which will be retried on the next access.
+    Fresh(Err),
+}
+
+impl<Err> Deref for Failure<'_, Err> {
+    type Target = Err;
+
+    fn deref(&self) -> &Err {
+        match self {
+            Failure::Cached(err) => err,
+            Failure::Fresh(err) => err,
+        }
+    }
+}
+
+impl<Err: fmt::Debug> fmt::Debug for Failure<'_, Err> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Cached(err) => f.debug_tuple("Cached").field(err).finish(),
+            Failure::Fresh(err) => f.debug_tuple("Fresh").field(err).finish(),
+        }
+    }
+}
+
+impl<Err: fmt::Display> fmt::Display for Failure<'_, Err> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+/// A lazily evaluated value whose evaluation may fail.
+///
+/// ~~~
+/// # use lazy_mt::TryThunk;
+/// let x = TryThunk::new(|| "42".parse::<u32>());
+/// assert_eq!(x.force().ok(), Some(&42));
+///
+/// let y = TryThunk::new(|| "x".parse::<u32>());
+/// assert!(y.force().is_err());
+/// ~~~
+pub struct TryThunk<E, V, Err> {
+    state: State,
+    inner: UnsafeCell<Inner<E, V, Err>>,
+    policy: ErrorPolicy,
+}
+
+// `inner` is only written by the thread that claimed the evaluation,
+// and only read once the thunk is evaluated.
+// The bounds are justified like for `Thunk`.
+unsafe impl<E, V, Err> Sync for TryThunk<E, V, Err>
+where
+    E: Send,
+    V: Send + Sync,
+    Err: Send + Sync,
+{
+}
+
+// A panicking evaluation poisons the thunk,
+// so its broken state can never be observed.
+impl<E, V: RefUnwindSafe, Err: RefUnwindSafe> RefUnwindSafe for TryThunk<E, V, Err> {}
+
+impl<E, V, Err> TryThunk<E, V, Err>
+where
+    E: TryEvaluate<V, Err>,
+{
+    /// Create a lazily evaluated value that caches errors.
+    pub fn new(e: E) -> Self {
+        Self::with_error_policy(e, ErrorPolicy::Cache)
+    }
+
+    /// Create a lazily evaluated value that behaves
+    /// according to the given policy if its evaluation fails.
+    ///
+    /// ~~~
+    /// # use lazy_mt::{ErrorPolicy, Failure, TryThunk};
+    /// let mut attempts = 0;
+    /// let x = TryThunk::with_error_policy(move || {
+    ///     attempts += 1;
+    ///     if attempts < 3 { Err(attempts) } else { Ok("done") }
+    /// }, ErrorPolicy::Retry);
+    ///
+    /// assert!(matches!(x.force(), Err(Failure::Fresh(1))));
+    /// assert!(matches!(x.force(), Err(Failure::Fresh(2))));
+    /// assert_eq!(x.force().ok(), Some(&"done"));
+    /// ~~~
+    pub fn with_error_policy(e: E, policy: ErrorPolicy) -> Self {
+        TryThunk {
+            state: State::new(UNEVALUATED),
+            inner: UnsafeCell::new(Unevaluated(e)),
+            policy,
+        }
+    }
+
+    /// Create a new, evaluated, thunk from a value.
+    pub fn evaluated(val: V) -> Self {
+        TryThunk {
+            state: State::new(EVALUATED),
+            inner: UnsafeCell::new(Value(val)),
+            policy: ErrorPolicy::Cache,
+        }
+    }
+
+    /// Force evaluation of a thunk, returning its value or error.
+    ///
+    /// If another thread is currently evaluating the thunk,
+    /// this blocks until that evaluation has finished.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `Thunk::force` if
+    /// the evaluation panicked or the thunk depends on itself.
+    pub fn force(&self) -> Result<&V, Failure<'_, Err>> {
+        if !self.state.is_evaluated() {
+            if let Some(err) = self.force_slow() {
+                return Err(Failure::Fresh(err));
+            }
+        }
+        // Safe because the thunk is evaluated,
+        // so `inner` is never written to again while `self` is borrowed.
+        match unsafe { &*self.inner.get() } {
+            Value(val) => Ok(val),
+            Error(err) => Err(Failure::Cached(err)),
+
+            // We just forced this thunk.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Return the current evaluation state of the thunk,
+    /// without forcing it.
+    ///
+    /// A thunk with a cached error counts as evaluated.
+    pub fn state(&self) -> ThunkState {
+        self.state.get()
+    }
+
+    /// Return an identifier of the thunk,
+    /// as used by `CycleError`.
+    pub fn id(&self) -> ThunkId {
+        ThunkId(self as *const Self as usize)
+    }
+
+    /// Evaluate the thunk, returning the error of
+    /// a failed evaluation that will be retried.
+    fn force_slow(&self) -> Option<Err> {
+        let claim = self
+            .state
+            .claim(self.id(), None)
+            .unwrap_or_else(|e| e.raise());
+        if let Claim::Evaluated = claim {
+            return None;
+        }
+        // Safe because we claimed the evaluation,
+        // so no other thread accesses `inner` until we are done.
+        let inner = unsafe { &mut *self.inner.get() };
+        let mut e = match mem::replace(inner, Evaluating) {
+            Unevaluated(e) => e,
+            _ => unreachable!(),
+        };
+        match self.state.evaluate(self.id(), || e.try_evaluate()) {
+            Ok(val) => *inner = Value(val),
+            Err(err) if self.policy == ErrorPolicy::Cache => *inner = Error(err),
+            Err(err) => {
+                *inner = Unevaluated(e);
+                self.state.finish(UNEVALUATED);
+                return Some(err);
+            }
+        }
+        self.state.finish(EVALUATED);
+        None
+    }
+}
+
+enum Inner<E, V, Err> {
+    Unevaluated(E),
+    Evaluating,
+    Value(V),
+    Error(Err),
+}
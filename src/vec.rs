@@ -0,0 +1,250 @@
+//! Vectors of lazily evaluated elements.
+
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::ops::Index;
+use std::panic::RefUnwindSafe;
+
+use crate::par::parallel;
+use crate::state::{Claim, Parking, Word, EVALUATED, UNEVALUATED};
+use crate::{ForceError, Panic, ThunkId, ThunkState};
+
+/// A vector whose elements are lazily evaluated.
+///
+/// All elements are computed by a single function from their index,
+/// and every element is computed at most once,
+/// on the first access from any thread.
+/// In contrast to a vector of thunks,
+/// this does not store an evaluator for every element,
+/// and every element only consists of its value and a state word.
+/// Threads waiting for elements share a single lock,
+/// which also holds the panics of poisoned elements.
+///
+/// ~~~
+/// # use lazy_mt::LazyVec;
+/// let squares = LazyVec::new(1000, |i| (i * i) as u64);
+/// assert_eq!(squares.get(10), None);
+/// assert_eq!(squares[10], 100);
+/// assert_eq!(squares.get(10), Some(&100));
+/// ~~~
+///
+/// Elements behave like thunks when
+/// their computation panics or depends on themselves.
+pub struct LazyVec<T, F = Box<dyn Fn(usize) -> T + Send + Sync>> {
+    f: F,
+    slots: Box<[Slot<T>]>,
+    /// Panics of poisoned elements by index.
+    parking: Parking<HashMap<usize, Panic>>,
+}
+
+struct Slot<T> {
+    word: Word,
+    value: UnsafeCell<Option<T>>,
+}
+
+// Every value is only written by the thread that claimed its evaluation,
+// and only read once it is evaluated.
+// The function may be called by several threads at the same time.
+unsafe impl<T: Send + Sync, F: Sync> Sync for LazyVec<T, F> {}
+
+// A panicking evaluation poisons its element,
+// so its broken state can never be observed.
+impl<T: RefUnwindSafe, F: RefUnwindSafe> RefUnwindSafe for LazyVec<T, F> {}
+
+impl<T> Slot<T> {
+    fn id(&self) -> ThunkId {
+        ThunkId(self as *const Self as usize)
+    }
+
+    fn get(&self) -> Option<&T> {
+        if !self.word.is_evaluated() {
+            return None;
+        }
+        // Safe because the element is evaluated,
+        // so `value` is never written to again while `self` is borrowed.
+        unsafe { &*self.value.get() }.as_ref()
+    }
+}
+
+impl<T, F: Fn(usize) -> T> LazyVec<T, F> {
+    /// Create a vector of `len` elements,
+    /// where the element at index `i` is lazily evaluated to `f(i)`.
+    pub fn new(len: usize, f: F) -> Self {
+        let slot = || Slot {
+            word: Word::new(UNEVALUATED),
+            value: UnsafeCell::new(None),
+        };
+        LazyVec {
+            f,
+            slots: (0..len).map(|_| slot()).collect(),
+            parking: Parking::new(HashMap::new()),
+        }
+    }
+
+    /// Force evaluation of the element at the given index,
+    /// returning its value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds, and
+    /// otherwise like `Thunk::force`.
+    pub fn force(&self, i: usize) -> &T {
+        self.try_force(i).unwrap_or_else(|e| e.raise())
+    }
+
+    /// Force evaluation of the element at the given index,
+    /// returning its value or the reason why it could not be evaluated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn try_force(&self, i: usize) -> Result<&T, ForceError> {
+        let slot = &self.slots[i];
+        if !slot.word.is_evaluated() {
+            self.force_slow(i)?
+        }
+        // We just forced this element.
+        Ok(slot.get().unwrap())
+    }
+
+    fn force_slow(&self, i: usize) -> Result<(), ForceError> {
+        let slot = &self.slots[i];
+        let parking = &self.parking;
+        if let Claim::Evaluate = slot.word.claim(parking, i, slot.id(), None)? {
+            let val = slot.word.evaluate(parking, i, slot.id(), || (self.f)(i));
+            // Safe because we claimed the evaluation,
+            // so no other thread accesses `value` until we are done.
+            unsafe { *slot.value.get() = Some(val) };
+            slot.word.finish(parking, i, EVALUATED);
+        }
+        Ok(())
+    }
+
+    /// Return an iterator over the values of all elements,
+    /// forcing them one after the other.
+    pub fn iter(&self) -> Iter<'_, T, F> {
+        Iter { vec: self, next: 0 }
+    }
+
+    /// Return the values of all elements, forcing them if necessary.
+    pub fn into_vec(self) -> Vec<T>
+    where
+        F: Sync,
+        T: Send + Sync,
+    {
+        self.force_all();
+        let slots = self.slots.into_vec().into_iter();
+        slots.map(|slot| slot.value.into_inner().unwrap()).collect()
+    }
+}
+
+impl<T: Send + Sync, F: Fn(usize) -> T + Sync> LazyVec<T, F> {
+    /// Force all elements in parallel.
+    ///
+    /// The elements are distributed over as many threads as
+    /// the system supports running in parallel.
+    ///
+    /// # Panics
+    ///
+    /// If forcing any element panics,
+    /// this resumes the panic after all other elements have been forced.
+    ///
+    /// ~~~
+    /// # use lazy_mt::LazyVec;
+    /// let v = LazyVec::new(100, |i| i + 1);
+    /// v.force_all();
+    /// assert!((0..v.len()).all(|i| v.get(i).is_some()));
+    /// assert_eq!(v.into_vec().iter().sum::<usize>(), 5050);
+    /// ~~~
+    ///
+    /// ~~~
+    /// # use lazy_mt::{LazyVec, ThunkState};
+    /// # use std::panic;
+    /// let v = LazyVec::new(8, |i| if i == 0 { panic!("oops") } else { i });
+    /// assert!(panic::catch_unwind(|| v.force_all()).is_err());
+    /// assert_eq!(v.state(0), ThunkState::Poisoned);
+    /// assert!((1..v.len()).all(|i| v.get(i).is_some()));
+    /// ~~~
+    pub fn force_all(&self) {
+        parallel(self.len(), |i| {
+            self.force(i);
+        });
+    }
+
+    /// Force all elements in parallel using `rayon`.
+    #[cfg(feature = "rayon")]
+    pub fn par_force(&self) {
+        use rayon::prelude::*;
+        (0..self.len()).into_par_iter().for_each(|i| {
+            self.force(i);
+        })
+    }
+}
+
+impl<T, F> LazyVec<T, F> {
+    /// Return the number of elements.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Return true if the vector has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Return the value of the element at the given index
+    /// if it has been evaluated, without forcing it.
+    pub fn get(&self, i: usize) -> Option<&T> {
+        self.slots.get(i)?.get()
+    }
+
+    /// Return the current evaluation state of the element at the given index,
+    /// without forcing it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn state(&self, i: usize) -> ThunkState {
+        self.slots[i].word.get()
+    }
+}
+
+impl<T, F: Fn(usize) -> T> Index<usize> for LazyVec<T, F> {
+    type Output = T;
+
+    fn index(&self, i: usize) -> &T {
+        self.force(i)
+    }
+}
+
+/// An iterator over the values of a `LazyVec`, forcing them one after the other.
+pub struct Iter<'a, T, F> {
+    vec: &'a LazyVec<T, F>,
+    next: usize,
+}
+
+impl<'a, T, F: Fn(usize) -> T> Iterator for Iter<'a, T, F> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.next >= self.vec.len() {
+            return None;
+        }
+        self.next += 1;
+        Some(self.vec.force(self.next - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.vec.len() - self.next;
+        (n, Some(n))
+    }
+}
+
+impl<'a, T, F: Fn(usize) -> T> IntoIterator for &'a LazyVec<T, F> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, F>;
+
+    fn into_iter(self) -> Iter<'a, T, F> {
+        self.iter()
+    }
+}